@@ -1,11 +1,12 @@
+use dragonfly_playground_rs::call_options::CallOptions;
+use dragonfly_playground_rs::redis_client::{
+    AsyncRedisClient, AsyncRedisClientPooled, AsyncRedisClientV1, get_connection_info,
+};
 use rand::Rng;
 use rand::distr::Alphanumeric;
 use redis::{ConnectionInfo, ProtocolVersion, RedisResult, ToRedisArgs};
 use std::env;
 use std::time::Duration;
-use dragonfly_playground_rs::redis_client::{
-    AsyncRedisClient, AsyncRedisClientPooled, AsyncRedisClientV1, get_connection_info,
-};
 
 pub fn build_random_items(
     count: usize,
@@ -39,8 +40,14 @@ impl BenchRedisClient {
         V: ToRedisArgs + Send + Sync,
     {
         match self {
-            BenchRedisClient::V1(c) => c.multi_set(items).await,
-            BenchRedisClient::Pooled(c) => c.multi_set(items).await,
+            BenchRedisClient::V1(c) => c
+                .multi_set(items, &CallOptions::default())
+                .await
+                .into_result(),
+            BenchRedisClient::Pooled(c) => c
+                .multi_set(items, &CallOptions::default())
+                .await
+                .into_result(),
         }
     }
 
@@ -54,8 +61,14 @@ impl BenchRedisClient {
         V: ToRedisArgs + Send + Sync,
     {
         match self {
-            BenchRedisClient::V1(c) => c.pipelined_multi_set_with_expiry(items, ttl).await,
-            BenchRedisClient::Pooled(c) => c.pipelined_multi_set_with_expiry(items, ttl).await,
+            BenchRedisClient::V1(c) => c
+                .pipelined_multi_set_with_expiry(items, ttl, &CallOptions::default())
+                .await
+                .into_result(),
+            BenchRedisClient::Pooled(c) => c
+                .pipelined_multi_set_with_expiry(items, ttl, &CallOptions::default())
+                .await
+                .into_result(),
         }
     }
 
@@ -69,8 +82,14 @@ impl BenchRedisClient {
         V: ToRedisArgs + Send + Sync,
     {
         match self {
-            BenchRedisClient::V1(c) => c.pipelined_set_with_expiry(items, ttl).await,
-            BenchRedisClient::Pooled(c) => c.pipelined_set_with_expiry(items, ttl).await,
+            BenchRedisClient::V1(c) => c
+                .pipelined_set_with_expiry(items, ttl, &CallOptions::default())
+                .await
+                .into_result(),
+            BenchRedisClient::Pooled(c) => c
+                .pipelined_set_with_expiry(items, ttl, &CallOptions::default())
+                .await
+                .into_result(),
         }
     }
 
@@ -83,8 +102,14 @@ impl BenchRedisClient {
         ttl: Duration,
     ) -> RedisResult<()> {
         match self {
-            BenchRedisClient::V1(c) => c.pipelined_set_with_expiry_manual(items, ttl).await,
-            BenchRedisClient::Pooled(c) => c.pipelined_set_with_expiry_manual(items, ttl).await,
+            BenchRedisClient::V1(c) => c
+                .pipelined_set_with_expiry_manual(items, ttl, &CallOptions::default())
+                .await
+                .into_result(),
+            BenchRedisClient::Pooled(c) => c
+                .pipelined_set_with_expiry_manual(items, ttl, &CallOptions::default())
+                .await
+                .into_result(),
         }
     }
 }
@@ -112,6 +137,7 @@ impl ClientType {
     }
 }
 
+#[allow(clippy::let_and_return)]
 pub fn create_connection_info() -> ConnectionInfo {
     let Some(server) = env::var("REDIS_BENCH_SERVER").ok() else {
         eprintln!("Skipping redis_end_to_end latency benchmark: REDIS_BENCH_SERVER not set");
@@ -131,7 +157,8 @@ pub fn create_connection_info() -> ConnectionInfo {
         ProtocolVersion::RESP3,
         username.clone(),
         password.clone(),
-    );
+    )
+    .expect("REDIS_BENCH_SERVER is not a valid address or URL");
     connection_info
 }
 
@@ -157,6 +184,7 @@ pub fn get_pooled_client_type(
     }
 }
 
+#[allow(clippy::ptr_arg, clippy::needless_as_bytes)]
 pub fn show_info(client_name: &String, client_cfg: String, items: &Vec<(String, Vec<u8>)>) {
     let total_items: usize = items.len();
     let total_size_of_key = items.iter().map(|(k, _)| k.len()).sum::<usize>();
@@ -1,16 +1,18 @@
+// Each bench uses only part of the shared helpers.
+#[allow(dead_code)]
 mod common;
 
 use crate::common::{
-    BenchRedisClient, ClientType, build_random_items,
-    get_v1_client_type, show_info,
+    BenchRedisClient, ClientType, build_random_items, get_v1_client_type, show_info,
 };
 use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+use dragonfly_playground_rs::redis_client::{AsyncRedisClientPooled, AsyncRedisClientV1};
 use std::env;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::runtime::Runtime;
-use dragonfly_playground_rs::redis_client::{AsyncRedisClientPooled, AsyncRedisClientV1};
 
+#[allow(clippy::let_and_return)]
 fn get_total_items() -> usize {
     let batch_size = env::var("REDIS_BENCH_TOTAL_ITEMS")
         .ok()
@@ -19,6 +21,7 @@ fn get_total_items() -> usize {
     batch_size
 }
 
+#[allow(clippy::let_and_return)]
 fn get_batch_size() -> usize {
     let batch_size = env::var("REDIS_BENCH_BATCH_SIZE")
         .ok()
@@ -1,11 +1,17 @@
+// Each bench uses only part of the shared helpers.
+#[allow(dead_code)]
 mod common;
 
 use crate::common::build_random_items;
 use chrono::Utc;
 use criterion::{Criterion, criterion_group, criterion_main};
+use dragonfly_playground_rs::expiry::ExpiryMode;
+use dragonfly_playground_rs::redis_client::{
+    build_mset_with_expire_pipeline, build_set_with_expiry_manual_pipeline,
+    build_set_with_expiry_pipeline, build_set_with_expiry_script_pipeline,
+};
 use std::hint::black_box;
 use std::time::Duration;
-use dragonfly_playground_rs::redis_client::{build_mset_with_expire_pipeline, build_set_with_expiry_manual_pipeline, build_set_with_expiry_pipeline};
 
 fn bench_pipeline_builders_10k(c: &mut Criterion) {
     let items = build_random_items(10_000, 80, 20);
@@ -13,22 +19,52 @@ fn bench_pipeline_builders_10k(c: &mut Criterion) {
 
     c.bench_function("build_mset_with_expire_pipeline_10k", |b| {
         b.iter(|| {
-            let pipeline = build_mset_with_expire_pipeline(black_box(&items), Utc::now(), ttl);
+            let pipeline = build_mset_with_expire_pipeline(
+                black_box(&items),
+                Utc::now(),
+                ttl,
+                ExpiryMode::ExAt,
+                None,
+            );
             black_box(pipeline);
         });
     });
 
     c.bench_function("build_set_with_expiry_pipeline_10k", |b| {
         b.iter(|| {
-            let pipeline = build_set_with_expiry_pipeline(black_box(&items), Utc::now(), ttl);
+            let pipeline = build_set_with_expiry_pipeline(
+                black_box(&items),
+                Utc::now(),
+                ttl,
+                ExpiryMode::ExAt,
+                None,
+            );
             black_box(pipeline);
         });
     });
 
     c.bench_function("build_set_with_expiry_manual_pipeline_10k", |b| {
         b.iter(|| {
-            let pipeline =
-                build_set_with_expiry_manual_pipeline(black_box(&items), Utc::now(), ttl);
+            let pipeline = build_set_with_expiry_manual_pipeline(
+                black_box(&items),
+                Utc::now(),
+                ttl,
+                ExpiryMode::ExAt,
+                None,
+            );
+            black_box(pipeline);
+        });
+    });
+
+    c.bench_function("build_set_with_expiry_script_pipeline_10k", |b| {
+        b.iter(|| {
+            let pipeline = build_set_with_expiry_script_pipeline(
+                black_box(&items),
+                Utc::now(),
+                ttl,
+                ExpiryMode::ExAt,
+                None,
+            );
             black_box(pipeline);
         });
     });
@@ -40,22 +76,52 @@ fn bench_pipeline_builders_100k(c: &mut Criterion) {
 
     c.bench_function("build_mset_with_expire_pipeline_100k", |b| {
         b.iter(|| {
-            let pipeline = build_mset_with_expire_pipeline(black_box(&items), Utc::now(), ttl);
+            let pipeline = build_mset_with_expire_pipeline(
+                black_box(&items),
+                Utc::now(),
+                ttl,
+                ExpiryMode::ExAt,
+                None,
+            );
             black_box(pipeline);
         });
     });
 
     c.bench_function("build_set_with_expiry_pipeline_100k", |b| {
         b.iter(|| {
-            let pipeline = build_set_with_expiry_pipeline(black_box(&items), Utc::now(), ttl);
+            let pipeline = build_set_with_expiry_pipeline(
+                black_box(&items),
+                Utc::now(),
+                ttl,
+                ExpiryMode::ExAt,
+                None,
+            );
             black_box(pipeline);
         });
     });
 
     c.bench_function("build_set_with_expiry_manual_pipeline_100k", |b| {
         b.iter(|| {
-            let pipeline =
-                build_set_with_expiry_manual_pipeline(black_box(&items), Utc::now(), ttl);
+            let pipeline = build_set_with_expiry_manual_pipeline(
+                black_box(&items),
+                Utc::now(),
+                ttl,
+                ExpiryMode::ExAt,
+                None,
+            );
+            black_box(pipeline);
+        });
+    });
+
+    c.bench_function("build_set_with_expiry_script_pipeline_100k", |b| {
+        b.iter(|| {
+            let pipeline = build_set_with_expiry_script_pipeline(
+                black_box(&items),
+                Utc::now(),
+                ttl,
+                ExpiryMode::ExAt,
+                None,
+            );
             black_box(pipeline);
         });
     });
@@ -0,0 +1,23 @@
+#!/usr/bin/env sh
+# Generates a throwaway CA plus server and client certificates for the `tls` compose profile.
+set -eu
+
+out="$(dirname "$0")/tls"
+mkdir -p "$out"
+cd "$out"
+
+openssl req -x509 -newkey rsa:2048 -nodes -days 365 -subj "/CN=dragonfly-playground-ca" \
+  -keyout ca.key -out ca.crt
+
+openssl req -newkey rsa:2048 -nodes -subj "/CN=localhost" -keyout server.key -out server.csr
+printf 'subjectAltName=DNS:localhost,IP:127.0.0.1\n' > server.ext
+openssl x509 -req -in server.csr -CA ca.crt -CAkey ca.key -CAcreateserial -days 365 \
+  -extfile server.ext -out server.crt
+
+openssl req -newkey rsa:2048 -nodes -subj "/CN=dragonfly-playground-client" \
+  -keyout client.key -out client.csr
+openssl x509 -req -in client.csr -CA ca.crt -CAkey ca.key -CAcreateserial -days 365 -out client.crt
+
+rm -f server.csr server.ext client.csr ca.srl
+# Dragonfly runs as a non-root user inside the container.
+chmod 644 ./*.key
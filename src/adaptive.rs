@@ -0,0 +1,270 @@
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Bounds and congestion signals of an adaptive write controller.
+///
+/// The controller looks at chunks in windows of `window`. A window whose mean chunk latency
+/// exceeds `target_latency` multiplies the batch size by `backoff`, as large chunks are slow to
+/// write; a window whose share of failed chunks, timeouts included, exceeds `max_error_rate`
+/// multiplies the parallelism by `backoff`, as the server is overloaded. A window with neither
+/// adds `batch_size_step` items and one parallel chunk. Both values stay within their bounds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AdaptiveOptions {
+    pub min_batch_size: usize,
+    pub max_batch_size: usize,
+    pub min_parallelism: usize,
+    pub max_parallelism: usize,
+    pub target_latency: Duration,
+    pub max_error_rate: f64,
+    pub window: usize,
+    pub batch_size_step: usize,
+    pub backoff: f64,
+}
+
+impl Default for AdaptiveOptions {
+    fn default() -> Self {
+        Self {
+            min_batch_size: 100,
+            max_batch_size: 10_000,
+            min_parallelism: 1,
+            max_parallelism: 16,
+            target_latency: Duration::from_millis(50),
+            max_error_rate: 0.0,
+            window: 16,
+            batch_size_step: 100,
+            backoff: 0.5,
+        }
+    }
+}
+
+impl AdaptiveOptions {
+    pub fn with_batch_size_bounds(mut self, min_batch_size: usize, max_batch_size: usize) -> Self {
+        self.min_batch_size = min_batch_size;
+        self.max_batch_size = max_batch_size;
+        self
+    }
+
+    pub fn with_parallelism_bounds(
+        mut self,
+        min_parallelism: usize,
+        max_parallelism: usize,
+    ) -> Self {
+        self.min_parallelism = min_parallelism;
+        self.max_parallelism = max_parallelism;
+        self
+    }
+
+    pub fn with_target_latency(mut self, target_latency: Duration) -> Self {
+        self.target_latency = target_latency;
+        self
+    }
+
+    pub fn with_max_error_rate(mut self, max_error_rate: f64) -> Self {
+        self.max_error_rate = max_error_rate;
+        self
+    }
+
+    pub fn with_window(mut self, window: usize) -> Self {
+        self.window = window;
+        self
+    }
+
+    pub fn with_batch_size_step(mut self, batch_size_step: usize) -> Self {
+        self.batch_size_step = batch_size_step;
+        self
+    }
+
+    pub fn with_backoff(mut self, backoff: f64) -> Self {
+        self.backoff = backoff;
+        self
+    }
+}
+
+/// Write chunk size and parallelism chosen by an adaptive controller.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AdaptiveSettings {
+    pub batch_size: usize,
+    pub parallelism: usize,
+}
+
+struct Window {
+    chunks: usize,
+    errors: usize,
+    latency: Duration,
+}
+
+/// AIMD controller of a client's write chunk size and parallelism, fed with the latency and
+/// result of every write chunk. Calls pick up the current settings when they start.
+pub(crate) struct AdaptiveController {
+    options: AdaptiveOptions,
+    state: Mutex<(AdaptiveSettings, Window)>,
+}
+
+impl AdaptiveController {
+    /// Controller starting from `initial`, with `options` normalised so that every bound is at
+    /// least one and no minimum exceeds its maximum.
+    pub(crate) fn new(mut options: AdaptiveOptions, initial: AdaptiveSettings) -> Self {
+        options.max_batch_size = options.max_batch_size.max(1);
+        options.min_batch_size = options.min_batch_size.clamp(1, options.max_batch_size);
+        options.max_parallelism = options.max_parallelism.max(1);
+        options.min_parallelism = options.min_parallelism.clamp(1, options.max_parallelism);
+        options.window = options.window.max(1);
+        let settings = AdaptiveSettings {
+            batch_size: initial
+                .batch_size
+                .clamp(options.min_batch_size, options.max_batch_size),
+            parallelism: initial
+                .parallelism
+                .clamp(options.min_parallelism, options.max_parallelism),
+        };
+        let window = Window {
+            chunks: 0,
+            errors: 0,
+            latency: Duration::ZERO,
+        };
+        Self {
+            options,
+            state: Mutex::new((settings, window)),
+        }
+    }
+
+    pub(crate) fn settings(&self) -> AdaptiveSettings {
+        self.state.lock().expect("adaptive lock poisoned").0
+    }
+
+    /// Records a write chunk that took `latency` and succeeded or not, a timed out chunk counting
+    /// as failed. Returns the new settings when this chunk completed a window and they changed.
+    pub(crate) fn observe(&self, latency: Duration, ok: bool) -> Option<AdaptiveSettings> {
+        let options = &self.options;
+        let mut state = self.state.lock().expect("adaptive lock poisoned");
+        let (settings, window) = &mut *state;
+        window.chunks += 1;
+        window.errors += usize::from(!ok);
+        window.latency += latency;
+        if window.chunks < options.window {
+            return None;
+        }
+
+        let error_rate = window.errors as f64 / window.chunks as f64;
+        let mean_latency = window.latency / window.chunks as u32;
+        *window = Window {
+            chunks: 0,
+            errors: 0,
+            latency: Duration::ZERO,
+        };
+        let previous = *settings;
+        let slow = mean_latency > options.target_latency;
+        let failing = error_rate > options.max_error_rate;
+        let back_off = |value: usize| (value as f64 * options.backoff) as usize;
+        if slow {
+            settings.batch_size = back_off(settings.batch_size).max(options.min_batch_size);
+        }
+        if failing {
+            settings.parallelism = back_off(settings.parallelism).max(options.min_parallelism);
+        }
+        if !slow && !failing {
+            settings.batch_size =
+                (settings.batch_size + options.batch_size_step).min(options.max_batch_size);
+            settings.parallelism = (settings.parallelism + 1).min(options.max_parallelism);
+        }
+        (*settings != previous).then_some(*settings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FAST: Duration = Duration::from_millis(1);
+    const SLOW: Duration = Duration::from_secs(1);
+
+    fn controller(batch_size: usize, parallelism: usize) -> AdaptiveController {
+        let options = AdaptiveOptions::default()
+            .with_batch_size_bounds(100, 1_000)
+            .with_parallelism_bounds(2, 8)
+            .with_target_latency(Duration::from_millis(50))
+            .with_window(4)
+            .with_batch_size_step(100);
+        let initial = AdaptiveSettings {
+            batch_size,
+            parallelism,
+        };
+        AdaptiveController::new(options, initial)
+    }
+
+    /// Feeds one window of chunks that took `latency`, `errors` of which failed.
+    fn window(
+        controller: &AdaptiveController,
+        latency: Duration,
+        errors: usize,
+    ) -> Option<AdaptiveSettings> {
+        let mut changed = None;
+        for chunk in 0..controller.options.window {
+            changed = controller.observe(latency, chunk >= errors);
+        }
+        changed
+    }
+
+    fn settings(batch_size: usize, parallelism: usize) -> AdaptiveSettings {
+        AdaptiveSettings {
+            batch_size,
+            parallelism,
+        }
+    }
+
+    #[test]
+    fn settings_change_only_at_the_end_of_a_window() {
+        let controller = controller(400, 4);
+        for _ in 0..3 {
+            assert_eq!(controller.observe(FAST, true), None);
+        }
+        assert_eq!(controller.observe(FAST, true), Some(settings(500, 5)));
+    }
+
+    #[test]
+    fn clean_windows_grow_both_additively() {
+        let controller = controller(400, 4);
+        assert_eq!(window(&controller, FAST, 0), Some(settings(500, 5)));
+        assert_eq!(window(&controller, FAST, 0), Some(settings(600, 6)));
+    }
+
+    #[test]
+    fn latency_shrinks_the_batch_size() {
+        let controller = controller(800, 4);
+        assert_eq!(window(&controller, SLOW, 0), Some(settings(400, 4)));
+    }
+
+    #[test]
+    fn errors_shrink_the_parallelism() {
+        let controller = controller(800, 4);
+        assert_eq!(window(&controller, FAST, 1), Some(settings(800, 2)));
+    }
+
+    #[test]
+    fn slow_failing_windows_shrink_both() {
+        let controller = controller(800, 8);
+        assert_eq!(window(&controller, SLOW, 4), Some(settings(400, 4)));
+    }
+
+    #[test]
+    fn backoff_stops_at_the_floors() {
+        let controller = controller(150, 3);
+        assert_eq!(window(&controller, SLOW, 4), Some(settings(100, 2)));
+        assert_eq!(window(&controller, SLOW, 4), None);
+        assert_eq!(controller.settings(), settings(100, 2));
+    }
+
+    #[test]
+    fn growth_stops_at_the_ceilings() {
+        let controller = controller(950, 7);
+        assert_eq!(window(&controller, FAST, 0), Some(settings(1_000, 8)));
+        assert_eq!(window(&controller, FAST, 0), None);
+        assert_eq!(controller.settings(), settings(1_000, 8));
+    }
+
+    #[test]
+    fn initial_settings_are_clamped_to_the_bounds() {
+        assert_eq!(controller(10, 1).settings(), settings(100, 2));
+        assert_eq!(controller(5_000, 64).settings(), settings(1_000, 8));
+    }
+}
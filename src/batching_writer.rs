@@ -0,0 +1,343 @@
+use crate::call_options::CallOptions;
+use crate::chunking::item_resp_len;
+use crate::redis_client::{AsyncRedisClient, AsyncRedisClientPooled};
+use crate::write_outcome::WriteOutcome;
+use futures::{StreamExt, stream};
+use redis::{ErrorKind, RedisError, RedisResult, ToRedisArgs};
+use std::pin::pin;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+use tracing::debug;
+
+/// When a `BatchingWriter` flushes and how it writes.
+///
+/// A batch starts with the first item that arrives and is flushed once it holds `max_items`
+/// items, once the next item would take it past `max_bytes` bytes of serialized keys and values,
+/// in which case that item starts the next batch, or `linger` after it started, whichever comes
+/// first. An item larger than `max_bytes` makes up a batch of its own. Up to
+/// `max_in_flight_batches` batches are written at once; after that batches stop forming and
+/// callers wait once `queue_capacity` items are queued.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BatchingOptions {
+    pub max_items: usize,
+    pub max_bytes: Option<usize>,
+    pub linger: Duration,
+    pub max_in_flight_batches: usize,
+    pub queue_capacity: usize,
+    /// When set, batches are written with `pipelined_multi_set_with_expiry` instead of `MSET`.
+    pub ttl: Option<Duration>,
+}
+
+impl Default for BatchingOptions {
+    fn default() -> Self {
+        Self {
+            max_items: 1000,
+            max_bytes: None,
+            linger: Duration::from_millis(5),
+            max_in_flight_batches: 4,
+            queue_capacity: 10_000,
+            ttl: None,
+        }
+    }
+}
+
+impl BatchingOptions {
+    pub fn with_max_items(mut self, max_items: usize) -> Self {
+        self.max_items = max_items;
+        self
+    }
+
+    pub fn with_max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = Some(max_bytes);
+        self
+    }
+
+    pub fn with_linger(mut self, linger: Duration) -> Self {
+        self.linger = linger;
+        self
+    }
+
+    pub fn with_max_in_flight_batches(mut self, max_in_flight_batches: usize) -> Self {
+        self.max_in_flight_batches = max_in_flight_batches;
+        self
+    }
+
+    pub fn with_queue_capacity(mut self, queue_capacity: usize) -> Self {
+        self.queue_capacity = queue_capacity;
+        self
+    }
+
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+}
+
+/// An item waiting in a `BatchingWriter` and the caller waiting for it.
+struct Pending<K, V> {
+    key: K,
+    value: V,
+    ack: oneshot::Sender<RedisResult<()>>,
+}
+
+/// Cloneable handle that coalesces single-item writes from many tasks into batched writes.
+///
+/// Items passed to `set` are queued for a background task, which groups them into batches as
+/// described by `BatchingOptions` and writes each batch with one call of the pooled client, so
+/// that its chunking, parallelism, retries and metrics apply. The task keeps running while any
+/// handle is alive; once the last one is dropped it flushes what is queued and exits.
+pub struct BatchingWriter<K, V> {
+    sender: mpsc::Sender<Pending<K, V>>,
+}
+
+impl<K, V> Clone for BatchingWriter<K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            sender: self.sender.clone(),
+        }
+    }
+}
+
+impl<K, V> BatchingWriter<K, V>
+where
+    K: ToRedisArgs + Send + Sync + 'static,
+    V: ToRedisArgs + Send + Sync + 'static,
+{
+    /// Starts the background task writing through `client`. Must be called within a Tokio
+    /// runtime.
+    pub fn new(client: Arc<AsyncRedisClientPooled>, options: BatchingOptions) -> Self {
+        let (sender, receiver) = mpsc::channel(options.queue_capacity.max(1));
+        tokio::spawn(run(client, receiver, options));
+        Self { sender }
+    }
+
+    /// Queues `key` and `value` and resolves once the chunk they were written in is acknowledged,
+    /// with that chunk's error if it failed, or with the reason it was not sent, e.g. a shutdown
+    /// error. Waits for room in the queue while it is full. Once queued, the item is written even
+    /// if the returned future is dropped.
+    pub async fn set(&self, key: K, value: V) -> RedisResult<()> {
+        let (ack, result) = oneshot::channel();
+        self.sender
+            .send(Pending { key, value, ack })
+            .await
+            .map_err(|_| closed_error())?;
+        result.await.map_err(|_| closed_error())?
+    }
+}
+
+async fn run<K, V>(
+    client: Arc<AsyncRedisClientPooled>,
+    receiver: mpsc::Receiver<Pending<K, V>>,
+    options: BatchingOptions,
+) where
+    K: ToRedisArgs + Send + Sync,
+    V: ToRedisArgs + Send + Sync,
+{
+    let batches = stream::unfold((receiver, None), |(mut receiver, mut carry)| async move {
+        let batch = next_batch(&mut receiver, &mut carry, &options).await?;
+        Some((batch, (receiver, carry)))
+    });
+    batches
+        .for_each_concurrent(options.max_in_flight_batches.max(1), |batch| {
+            flush(&client, batch, options.ttl)
+        })
+        .await;
+    debug!("Batching writer for {} stopped", client.server_adder());
+}
+
+/// Waits for the first item of the next batch, or takes the one left in `carry`, and collects more
+/// until the batch is full or its linger time has passed. An item that would take the batch past
+/// `max_bytes` is left in `carry` to start the next one. Returns `None` once every handle is gone
+/// and the queue is empty.
+async fn next_batch<K: ToRedisArgs, V: ToRedisArgs>(
+    receiver: &mut mpsc::Receiver<Pending<K, V>>,
+    carry: &mut Option<Pending<K, V>>,
+    options: &BatchingOptions,
+) -> Option<Vec<Pending<K, V>>> {
+    let first = match carry.take() {
+        Some(first) => first,
+        None => receiver.recv().await?,
+    };
+    let max_items = options.max_items.max(1);
+    let max_bytes = options.max_bytes.unwrap_or(usize::MAX);
+    let item_len = |item: &Pending<K, V>| match options.max_bytes {
+        Some(_) => item_resp_len(&item.key, &item.value),
+        None => 0,
+    };
+    let mut bytes = item_len(&first);
+    let mut batch = vec![first];
+    let mut linger = pin!(tokio::time::sleep(options.linger));
+    while batch.len() < max_items && bytes < max_bytes {
+        let item = tokio::select! {
+            item = receiver.recv() => item,
+            () = &mut linger => None,
+        };
+        let Some(item) = item else {
+            break;
+        };
+        let len = item_len(&item);
+        if bytes + len > max_bytes {
+            *carry = Some(item);
+            break;
+        }
+        bytes += len;
+        batch.push(item);
+    }
+    Some(batch)
+}
+
+/// Writes `batch` and hands each caller the result of its item.
+async fn flush<K, V>(
+    client: &AsyncRedisClientPooled,
+    batch: Vec<Pending<K, V>>,
+    ttl: Option<Duration>,
+) where
+    K: ToRedisArgs + Send + Sync,
+    V: ToRedisArgs + Send + Sync,
+{
+    let (items, acks): (Vec<(K, V)>, Vec<_>) = batch
+        .into_iter()
+        .map(|pending| ((pending.key, pending.value), pending.ack))
+        .unzip();
+    debug!("Flushing batch of {} items", items.len());
+    let call = CallOptions::default();
+    let outcome = match ttl {
+        Some(ttl) => {
+            client
+                .pipelined_multi_set_with_expiry(&items, ttl, &call)
+                .await
+        }
+        None => client.multi_set(&items, &call).await,
+    };
+    for (ack, result) in acks.into_iter().zip(item_results(outcome, items.len())) {
+        // The caller may have stopped waiting for the result.
+        let _ = ack.send(result);
+    }
+}
+
+/// Splits the outcome of a batch of `len` items into the result of each item.
+fn item_results(outcome: WriteOutcome, len: usize) -> Vec<RedisResult<()>> {
+    let mut results: Vec<RedisResult<()>> = (0..len).map(|_| Ok(())).collect();
+    for failure in &outcome.failed {
+        for idx in failure.indices() {
+            results[idx] = Err(RedisError::from((
+                failure.error.kind(),
+                "Batched write failed",
+                failure.error.to_string(),
+            )));
+        }
+    }
+    let not_attempted: usize = outcome.not_attempted.iter().map(|r| r.len()).sum();
+    for idx in outcome.not_attempted.iter().cloned().flatten() {
+        results[idx] = Err(match outcome.stop() {
+            Some(stop) => stop.error(not_attempted),
+            None => RedisError::from((ErrorKind::ClientError, "Batched write not attempted")),
+        });
+    }
+    results
+}
+
+fn closed_error() -> RedisError {
+    RedisError::from((ErrorKind::ClientError, "Batching writer is closed"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::call_options::is_shut_down;
+    use crate::cancellation::Stop;
+    use crate::write_outcome::ChunkFailure;
+    use std::time::Instant;
+
+    type Receiver = mpsc::Receiver<Pending<usize, String>>;
+
+    /// Queue of `values` keyed by their position, whose acks nobody waits for.
+    fn queue(values: &[&str]) -> (mpsc::Sender<Pending<usize, String>>, Receiver) {
+        let (sender, receiver) = mpsc::channel(values.len().max(1));
+        for (key, value) in values.iter().enumerate() {
+            let (ack, _) = oneshot::channel();
+            let pending = Pending {
+                key,
+                value: value.to_string(),
+                ack,
+            };
+            sender.try_send(pending).expect("queue has room");
+        }
+        (sender, receiver)
+    }
+
+    /// Keys of the batches `next_batch` forms until the queue is drained.
+    async fn batches(mut receiver: Receiver, options: &BatchingOptions) -> Vec<Vec<usize>> {
+        let mut carry = None;
+        let mut batches = Vec::new();
+        while let Some(batch) = next_batch(&mut receiver, &mut carry, options).await {
+            batches.push(batch.into_iter().map(|pending| pending.key).collect());
+        }
+        batches
+    }
+
+    fn lingering() -> BatchingOptions {
+        BatchingOptions::default().with_linger(Duration::from_secs(3600))
+    }
+
+    #[tokio::test]
+    async fn flushes_by_count() {
+        let (sender, receiver) = queue(&["a"; 5]);
+        drop(sender);
+        let options = lingering().with_max_items(2);
+        assert_eq!(
+            batches(receiver, &options).await,
+            [vec![0, 1], vec![2, 3], vec![4]]
+        );
+    }
+
+    #[tokio::test]
+    async fn flushes_by_bytes_and_carries_the_overflowing_item_over() {
+        let values = ["aaaa", "bbbb", "cccc", "dddddddddddddddddddd", "eeee"];
+        let (sender, receiver) = queue(&values);
+        drop(sender);
+        let item = item_resp_len(&0usize, &"aaaa");
+        let options = lingering().with_max_bytes(2 * item + 1);
+        // The third item would overflow the first batch and starts the second one; the long
+        // fourth item makes up a batch of its own.
+        assert_eq!(
+            batches(receiver, &options).await,
+            [vec![0, 1], vec![2], vec![3], vec![4]]
+        );
+    }
+
+    #[tokio::test]
+    async fn flushes_by_linger() {
+        let (_sender, mut receiver) = queue(&["a", "b"]);
+        let linger = Duration::from_millis(20);
+        let options = BatchingOptions::default().with_linger(linger);
+        let started = Instant::now();
+        let batch = next_batch(&mut receiver, &mut None, &options).await;
+        assert_eq!(batch.map(|batch| batch.len()), Some(2));
+        assert!(started.elapsed() >= linger);
+    }
+
+    #[test]
+    #[allow(clippy::single_range_in_vec_init)]
+    fn item_results_carry_the_reason_of_each_item() {
+        let outcome = WriteOutcome {
+            succeeded: vec![0..1, 3..4],
+            failed: vec![ChunkFailure {
+                ranges: vec![1..2],
+                error: RedisError::from((ErrorKind::ReadOnly, "test error")),
+            }],
+            not_attempted: vec![2..3, 4..5],
+            shut_down: true,
+            ..WriteOutcome::default()
+        };
+        let results = item_results(outcome, 5);
+        assert!(results[0].is_ok() && results[3].is_ok());
+        assert_eq!(results[1].as_ref().unwrap_err().kind(), ErrorKind::ReadOnly);
+        for idx in [2, 4] {
+            let err = results[idx].as_ref().unwrap_err();
+            assert!(is_shut_down(err), "{err}");
+            assert_eq!(err.to_string(), Stop::ShutDown.error(2).to_string());
+        }
+    }
+}
@@ -0,0 +1,91 @@
+use redis::{ErrorKind, RedisError};
+use std::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
+
+/// Limits of a single client call, passed to every data method of `AsyncRedisClient`.
+///
+/// Once the deadline passes, the call stops starting chunks: writes report the rest as not
+/// attempted, with `WriteOutcome::deadline_exceeded` set, and reads fail with an error for which
+/// `is_deadline_exceeded` holds. Cancelling `cancellation` stops the call the same way, with
+/// `WriteOutcome::cancelled` set and errors for which `is_cancelled` holds. Chunks already in
+/// flight are not interrupted, and retries of a chunk do not wait past the deadline. The default
+/// has neither.
+#[derive(Debug, Clone, Default)]
+pub struct CallOptions {
+    pub deadline: Option<Instant>,
+    pub cancellation: Option<CancellationToken>,
+}
+
+impl CallOptions {
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Sets the deadline to `timeout` from now.
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        self.with_deadline(Instant::now() + timeout)
+    }
+
+    /// Stops the call from starting new chunks once `cancellation` is cancelled. One token may be
+    /// shared by many calls, e.g. to stop all of them on Ctrl-C.
+    pub fn with_cancellation(mut self, cancellation: CancellationToken) -> Self {
+        self.cancellation = Some(cancellation);
+        self
+    }
+}
+
+/// Code of the extension error of a call whose deadline passed.
+pub(crate) const DEADLINE_EXCEEDED: &str = "DEADLINE_EXCEEDED";
+/// Code of the extension error of a cancelled call.
+pub(crate) const CANCELLED: &str = "CANCELLED";
+/// Code of the extension error of a call on a client that was shut down.
+pub(crate) const SHUT_DOWN: &str = "SHUT_DOWN";
+
+/// Whether `err` reports a call whose deadline passed before all of its chunks were sent.
+pub fn is_deadline_exceeded(err: &RedisError) -> bool {
+    err.kind() == ErrorKind::ExtensionError && err.code() == Some(DEADLINE_EXCEEDED)
+}
+
+/// Whether `err` reports a call that was cancelled before all of its chunks were sent.
+pub fn is_cancelled(err: &RedisError) -> bool {
+    err.kind() == ErrorKind::ExtensionError && err.code() == Some(CANCELLED)
+}
+
+/// Whether `err` reports a call whose client was shut down before all of its chunks were sent.
+pub fn is_shut_down(err: &RedisError) -> bool {
+    err.kind() == ErrorKind::ExtensionError && err.code() == Some(SHUT_DOWN)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cancellation::Stop;
+    use crate::retry::is_retryable;
+    use crate::sentinel_client::is_failover_error;
+
+    #[test]
+    fn stopped_calls_report_their_own_errors() {
+        let predicates: [fn(&RedisError) -> bool; 3] =
+            [is_deadline_exceeded, is_cancelled, is_shut_down];
+        let stops = [Stop::DeadlineExceeded, Stop::Cancelled, Stop::ShutDown];
+        for (i, stop) in stops.into_iter().enumerate() {
+            let err = stop.error(3);
+            for (j, predicate) in predicates.iter().enumerate() {
+                assert_eq!(predicate(&err), i == j, "{err}");
+            }
+            assert_ne!(err.kind(), ErrorKind::ClientError);
+            assert!(!is_retryable(&err), "{err} should not be retried");
+            assert!(!is_failover_error(&err), "{err} should not fail over");
+            assert!(err.to_string().contains("3 items not attempted"), "{err}");
+        }
+    }
+
+    #[test]
+    fn other_errors_match_no_predicate() {
+        let err = RedisError::from((ErrorKind::ClientError, "Call deadline exceeded"));
+        assert!(!is_deadline_exceeded(&err));
+        assert!(!is_cancelled(&err));
+        assert!(!is_shut_down(&err));
+    }
+}
@@ -0,0 +1,144 @@
+use crate::call_options::{CANCELLED, CallOptions, SHUT_DOWN};
+use crate::timeouts::deadline_error;
+use redis::RedisError;
+use std::time::Instant;
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::TaskTracker;
+use tokio_util::task::task_tracker::TaskTrackerToken;
+
+/// Why a call stopped starting chunks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Stop {
+    DeadlineExceeded,
+    Cancelled,
+    ShutDown,
+}
+
+impl Stop {
+    /// Error of a call that stopped before `not_attempted` of its items were sent.
+    pub(crate) fn error(self, not_attempted: usize) -> RedisError {
+        match self {
+            Stop::DeadlineExceeded => deadline_error(not_attempted),
+            Stop::Cancelled => cancelled_error(not_attempted),
+            Stop::ShutDown => shut_down_error(not_attempted),
+        }
+    }
+}
+
+/// Decides whether a client call may start its next chunk: not once its deadline has passed, its
+/// cancellation token has been cancelled or its client has been shut down. Chunks already started
+/// are never interrupted, so that the call can still report what they wrote.
+///
+/// The gate also counts as a call in flight on its client's `TaskTracker` until it is dropped, so
+/// that shutting the client down can wait for the chunks it let through.
+#[derive(Debug, Clone)]
+pub(crate) struct ChunkGate {
+    deadline: Option<Instant>,
+    cancellation: Option<CancellationToken>,
+    shutdown: CancellationToken,
+    _in_flight: TaskTrackerToken,
+}
+
+impl ChunkGate {
+    /// Gate of a call made with `call` on a client that stops on `shutdown` and tracks its calls
+    /// with `calls`.
+    pub(crate) fn new(
+        call: &CallOptions,
+        shutdown: &CancellationToken,
+        calls: &TaskTracker,
+    ) -> Self {
+        Self {
+            deadline: call.deadline,
+            cancellation: call.cancellation.clone(),
+            shutdown: shutdown.clone(),
+            _in_flight: calls.token(),
+        }
+    }
+
+    /// Deadline of the call, which also bounds the retries of its chunks.
+    pub(crate) fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    /// Reason not to start another chunk, if any. Shutdown wins over cancellation, which wins over
+    /// the deadline.
+    pub(crate) fn closed(&self) -> Option<Stop> {
+        if self.shutdown.is_cancelled() {
+            Some(Stop::ShutDown)
+        } else if self
+            .cancellation
+            .as_ref()
+            .is_some_and(CancellationToken::is_cancelled)
+        {
+            Some(Stop::Cancelled)
+        } else if self
+            .deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            Some(Stop::DeadlineExceeded)
+        } else {
+            None
+        }
+    }
+}
+
+/// Error of a call that was cancelled before `not_attempted` of its items were sent. An
+/// extension error for the same reason as `deadline_error`.
+pub(crate) fn cancelled_error(not_attempted: usize) -> RedisError {
+    redis::make_extension_error(
+        CANCELLED.to_string(),
+        Some(format!(
+            "Call cancelled, {not_attempted} items not attempted"
+        )),
+    )
+}
+
+/// Error of a call whose client was shut down before `not_attempted` of its items were sent.
+pub(crate) fn shut_down_error(not_attempted: usize) -> RedisError {
+    redis::make_extension_error(
+        SHUT_DOWN.to_string(),
+        Some(format!(
+            "Client shut down, {not_attempted} items not attempted"
+        )),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn shutdown_wins_over_cancellation_and_the_deadline() {
+        let (shutdown, calls) = (CancellationToken::new(), TaskTracker::new());
+        let cancellation = CancellationToken::new();
+        let call = CallOptions::default()
+            .with_deadline(Instant::now() + Duration::from_secs(60))
+            .with_cancellation(cancellation.clone());
+        let gate = ChunkGate::new(&call, &shutdown, &calls);
+        assert_eq!(gate.closed(), None);
+
+        let past = CallOptions::default().with_deadline(Instant::now());
+        let expired = ChunkGate::new(&past, &shutdown, &calls);
+        assert_eq!(expired.closed(), Some(Stop::DeadlineExceeded));
+
+        cancellation.cancel();
+        assert_eq!(gate.closed(), Some(Stop::Cancelled));
+        shutdown.cancel();
+        assert_eq!(gate.closed(), Some(Stop::ShutDown));
+        assert_eq!(expired.closed(), Some(Stop::ShutDown));
+    }
+
+    #[tokio::test]
+    async fn calls_are_in_flight_until_their_gate_is_dropped() {
+        let (shutdown, calls) = (CancellationToken::new(), TaskTracker::new());
+        let gate = ChunkGate::new(&CallOptions::default(), &shutdown, &calls);
+        calls.close();
+        let wait = tokio::time::timeout(Duration::from_millis(20), calls.wait()).await;
+        assert!(wait.is_err(), "shutdown must wait for the open call");
+
+        drop(gate);
+        let wait = tokio::time::timeout(Duration::from_millis(20), calls.wait()).await;
+        assert!(wait.is_ok());
+    }
+}
@@ -0,0 +1,508 @@
+use crate::expiry::Expiry;
+use crate::transaction::CompareAndSet;
+use futures::{Stream, StreamExt, stream};
+use redis::{RedisWrite, ToRedisArgs};
+use std::io;
+use std::ops::Range;
+
+/// An item of a write call: a key and its value, optionally with the item's own expiry or the value
+/// it is expected to replace.
+pub trait ChunkItem {
+    type Key: ToRedisArgs;
+
+    fn key(&self) -> &Self::Key;
+
+    /// Size of the RESP encoding of the item's key and value arguments.
+    fn resp_len(&self) -> usize;
+}
+
+impl<K: ToRedisArgs, V: ToRedisArgs> ChunkItem for (K, V) {
+    type Key = K;
+
+    fn key(&self) -> &K {
+        &self.0
+    }
+
+    fn resp_len(&self) -> usize {
+        item_resp_len(&self.0, &self.1)
+    }
+}
+
+impl<K: ToRedisArgs, V: ToRedisArgs> ChunkItem for (K, V, Expiry) {
+    type Key = K;
+
+    fn key(&self) -> &K {
+        &self.0
+    }
+
+    fn resp_len(&self) -> usize {
+        item_resp_len(&self.0, &self.1)
+    }
+}
+
+impl<K: ToRedisArgs, V: ToRedisArgs> ChunkItem for CompareAndSet<K, V> {
+    type Key = K;
+
+    fn key(&self) -> &K {
+        &self.key
+    }
+
+    fn resp_len(&self) -> usize {
+        item_resp_len(&self.key, &self.value)
+    }
+}
+
+impl<T: ChunkItem> ChunkItem for &T {
+    type Key = T::Key;
+
+    fn key(&self) -> &T::Key {
+        (*self).key()
+    }
+
+    fn resp_len(&self) -> usize {
+        (*self).resp_len()
+    }
+}
+
+/// Bytes the commands of a write path add to a chunk's frames on top of the RESP encoding of its
+/// keys and values: command headers and names, options, expiry arguments and repeated keys.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FrameCost {
+    /// Bytes added once per chunk, e.g. the header and name of its `MSET`.
+    pub per_chunk: usize,
+    /// Bytes added per item, e.g. the header, name and timestamp of its `EXPIREAT`.
+    pub per_item: usize,
+    /// Whether each item's key is sent a second time, e.g. as the argument of its `EXPIREAT`.
+    pub repeats_key: bool,
+}
+
+impl FrameCost {
+    /// Frame of a single `MSET` of the chunk's items.
+    pub const MSET: FrameCost = FrameCost {
+        // The header, then `$4\r\nMSET\r\n`.
+        per_chunk: MAX_ARRAY_HEADER_LEN + 10,
+        per_item: 0,
+        repeats_key: false,
+    };
+
+    /// The same cost with the `MULTI` and `EXEC` that wrap a chunk in a transaction.
+    pub fn with_transaction(mut self) -> Self {
+        self.per_chunk += command_len("MULTI", 0) + command_len("EXEC", 0);
+        self
+    }
+
+    /// Bytes `item` adds to a chunk.
+    pub fn item_len<T: ChunkItem>(&self, item: &T) -> usize {
+        let key_len = match self.repeats_key {
+            true => args_resp_len(item.key()),
+            false => 0,
+        };
+        item.resp_len() + self.per_item + key_len
+    }
+}
+
+/// How write paths split their input into chunks.
+///
+/// Byte budgets are measured as the RESP encoding of each item's key and value arguments
+/// (`$<len>\r\n<data>\r\n`) plus the `FrameCost` of the write path, i.e. the size of the frames
+/// the chunk is sent as, which is what `DFLY_pipeline_buffer_limit` is compared against. An item
+/// larger than the budget on its own is sent as a single-item chunk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkingStrategy {
+    /// At most `n` items per chunk.
+    Items(usize),
+    /// At most `n` bytes of serialized frames per chunk.
+    Bytes(usize),
+    /// Whichever of the two limits is reached first.
+    ItemsAndBytes { max_items: usize, max_bytes: usize },
+}
+
+impl ChunkingStrategy {
+    /// Chunking by item count, optionally capped by a byte budget as well.
+    pub fn new(batch_size: usize, max_chunk_bytes: Option<usize>) -> Self {
+        match max_chunk_bytes {
+            Some(max_bytes) => ChunkingStrategy::ItemsAndBytes {
+                max_items: batch_size,
+                max_bytes,
+            },
+            None => ChunkingStrategy::Items(batch_size),
+        }
+    }
+
+    /// The same strategy with its item limit set to `max_items`, keeping any byte budget.
+    pub fn with_max_items(self, max_items: usize) -> Self {
+        match self {
+            ChunkingStrategy::Items(_) => ChunkingStrategy::Items(max_items),
+            ChunkingStrategy::Bytes(max_bytes)
+            | ChunkingStrategy::ItemsAndBytes { max_bytes, .. } => {
+                ChunkingStrategy::ItemsAndBytes {
+                    max_items,
+                    max_bytes,
+                }
+            }
+        }
+    }
+
+    /// Maximum number of items per chunk, if bounded.
+    pub fn max_items(&self) -> Option<usize> {
+        match *self {
+            ChunkingStrategy::Items(max_items) => Some(max_items),
+            ChunkingStrategy::Bytes(_) => None,
+            ChunkingStrategy::ItemsAndBytes { max_items, .. } => Some(max_items),
+        }
+    }
+
+    fn limits(&self) -> (usize, usize) {
+        match *self {
+            ChunkingStrategy::Items(max_items) => (max_items.max(1), usize::MAX),
+            ChunkingStrategy::Bytes(max_bytes) => (usize::MAX, max_bytes),
+            ChunkingStrategy::ItemsAndBytes {
+                max_items,
+                max_bytes,
+            } => (max_items.max(1), max_bytes),
+        }
+    }
+
+    /// Index ranges of the chunks `items` is split into when written with frames of `cost`.
+    pub fn ranges<T: ChunkItem>(&self, items: &[T], cost: FrameCost) -> Vec<Range<usize>> {
+        self.ranges_by(items, cost.per_chunk, |_| false, |item| cost.item_len(item))
+    }
+
+    /// Index ranges of the chunks `items` is split into when each run of items with the same
+    /// `segment`, which maps an item's index to its segment, is sent as frames of `cost` of its
+    /// own, e.g. one `MULTI`/`MSET`/`EXEC` per hash slot. `per_chunk` is charged per segment.
+    pub fn segmented_ranges<T: ChunkItem, S: PartialEq>(
+        &self,
+        items: &[T],
+        cost: FrameCost,
+        segment: impl Fn(usize) -> S,
+    ) -> Vec<Range<usize>> {
+        let starts_segment = |idx: usize| idx > 0 && segment(idx) != segment(idx - 1);
+        self.ranges_by(items, cost.per_chunk, starts_segment, |item| {
+            cost.item_len(item)
+        })
+    }
+
+    /// Index ranges of the chunks a key-only input such as an `MGET` is split into.
+    pub fn key_ranges<K: ToRedisArgs>(&self, keys: &[K]) -> Vec<Range<usize>> {
+        self.ranges_by(keys, 0, |_| false, args_resp_len)
+    }
+
+    /// Ranges of chunks of at most `max_items` items, each of which, unless it is a single item,
+    /// takes at most `max_bytes` counting the `item_len` of its items and `per_chunk` for the
+    /// chunk and for every item within it for which `starts_segment` holds.
+    fn ranges_by<T>(
+        &self,
+        items: &[T],
+        per_chunk: usize,
+        starts_segment: impl Fn(usize) -> bool,
+        item_len: impl Fn(&T) -> usize,
+    ) -> Vec<Range<usize>> {
+        let (max_items, max_bytes) = self.limits();
+        if max_bytes == usize::MAX {
+            return (0..items.len())
+                .step_by(max_items)
+                .map(|start| start..(start + max_items).min(items.len()))
+                .collect();
+        }
+
+        let mut ranges = Vec::new();
+        let mut start = 0;
+        let mut chunk_bytes = per_chunk;
+        for (idx, item) in items.iter().enumerate() {
+            let item_bytes = item_len(item);
+            let chunk_items = idx - start;
+            let segment_bytes = match chunk_items > 0 && starts_segment(idx) {
+                true => per_chunk,
+                false => 0,
+            };
+            if chunk_items > 0
+                && (chunk_items >= max_items
+                    || chunk_bytes + segment_bytes + item_bytes > max_bytes)
+            {
+                ranges.push(start..idx);
+                start = idx;
+                chunk_bytes = per_chunk;
+            } else {
+                chunk_bytes += segment_bytes;
+            }
+            chunk_bytes += item_bytes;
+        }
+        if start < items.len() {
+            ranges.push(start..items.len());
+        }
+        ranges
+    }
+
+    /// Splits `items` into chunks written with frames of `cost`, paired with the index range each
+    /// covers.
+    pub fn split<'a, T: ChunkItem>(
+        &self,
+        items: &'a [T],
+        cost: FrameCost,
+    ) -> impl Iterator<Item = (Range<usize>, &'a [T])> {
+        self.ranges(items, cost)
+            .into_iter()
+            .map(move |range| (range.clone(), &items[range]))
+    }
+
+    /// Groups the items of `items` into chunks written with frames of `cost` as the stream yields
+    /// them, paired with the range of stream positions each covers. Only the chunk being filled is
+    /// buffered, plus, with a byte budget, the item that overflowed it. A chunk that reaches the
+    /// item limit is yielded without waiting for the next item.
+    pub fn chunk_stream<T: ChunkItem>(
+        &self,
+        items: impl Stream<Item = T>,
+        cost: FrameCost,
+    ) -> impl Stream<Item = (Range<usize>, Vec<T>)> {
+        let (max_items, max_bytes) = self.limits();
+        let state = (Box::pin(items), None, 0);
+        stream::unfold(state, move |(mut items, mut overflow, start)| async move {
+            let mut chunk = Vec::new();
+            let mut chunk_bytes = cost.per_chunk;
+            while chunk.len() < max_items {
+                let item: T = match overflow.take() {
+                    Some(item) => item,
+                    None => match items.next().await {
+                        Some(item) => item,
+                        None => break,
+                    },
+                };
+                if max_bytes != usize::MAX {
+                    let item_bytes = cost.item_len(&item);
+                    if !chunk.is_empty() && chunk_bytes + item_bytes > max_bytes {
+                        overflow = Some(item);
+                        break;
+                    }
+                    chunk_bytes += item_bytes;
+                }
+                chunk.push(item);
+            }
+            if chunk.is_empty() {
+                return None;
+            }
+            let end = start + chunk.len();
+            Some(((start..end, chunk), (items, overflow, end)))
+        })
+    }
+
+    /// Splits `keys` into chunks, paired with the index range each covers.
+    pub fn split_keys<'a, K: ToRedisArgs>(
+        &self,
+        keys: &'a [K],
+    ) -> impl Iterator<Item = (Range<usize>, &'a [K])> {
+        self.key_ranges(keys)
+            .into_iter()
+            .map(move |range| (range.clone(), &keys[range]))
+    }
+}
+
+/// Size of a RESP bulk string carrying `len` bytes of payload.
+pub fn resp_bulk_len(len: usize) -> usize {
+    // `$` + decimal length + `\r\n` + payload + `\r\n`
+    1 + decimal_len(len as u64) + 2 + len + 2
+}
+
+/// Size of the header of a RESP array of `len` elements.
+pub fn resp_array_header_len(len: usize) -> usize {
+    // `*` + decimal length + `\r\n`
+    1 + decimal_len(len as u64) + 2
+}
+
+/// Bound of the header of the arrays commands with many arguments, such as `MSET`, are sent as.
+pub const MAX_ARRAY_HEADER_LEN: usize = 1 + 10 + 2;
+
+/// Size of the frame of command `name` with `args` arguments, not counting the arguments.
+pub fn command_len(name: &str, args: usize) -> usize {
+    resp_array_header_len(args + 1) + resp_bulk_len(name.len())
+}
+
+/// Number of decimal digits of `value`.
+pub fn decimal_len(value: u64) -> usize {
+    value.checked_ilog10().unwrap_or(0) as usize + 1
+}
+
+/// Size of the RESP encoding of the key and value arguments of a single item.
+pub fn item_resp_len<K: ToRedisArgs, V: ToRedisArgs>(key: &K, value: &V) -> usize {
+    args_resp_len(key) + args_resp_len(value)
+}
+
+/// Size of the RESP encoding of the arguments `value` expands to.
+pub fn args_resp_len<T: ToRedisArgs>(value: &T) -> usize {
+    let mut counter = RespLenCounter(0);
+    value.write_redis_args(&mut counter);
+    counter.0
+}
+
+struct RespLenCounter(usize);
+
+struct ArgLenWriter<'a> {
+    total: &'a mut usize,
+    len: usize,
+}
+
+impl io::Write for ArgLenWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.len += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for ArgLenWriter<'_> {
+    fn drop(&mut self) {
+        *self.total += resp_bulk_len(self.len);
+    }
+}
+
+impl RedisWrite for RespLenCounter {
+    fn write_arg(&mut self, arg: &[u8]) {
+        self.0 += resp_bulk_len(arg.len());
+    }
+
+    fn writer_for_next_arg(&mut self) -> impl io::Write + '_ {
+        ArgLenWriter {
+            total: &mut self.0,
+            len: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Items of `len` bytes each, as counted by `ChunkItem::resp_len`. `len` must be at least 25.
+    fn items(lens: &[usize]) -> Vec<(String, String)> {
+        lens.iter()
+            .enumerate()
+            .map(|(i, &len)| {
+                // `k<i>` takes 8 bytes as a bulk string, the value and its 7 byte frame the rest.
+                let item = (format!("k{i}"), "v".repeat(len - 8 - 7));
+                assert_eq!(item.resp_len(), len);
+                item
+            })
+            .collect()
+    }
+
+    async fn stream_ranges(
+        chunking: ChunkingStrategy,
+        items: Vec<(String, String)>,
+        cost: FrameCost,
+    ) -> Vec<Range<usize>> {
+        chunking
+            .chunk_stream(stream::iter(items), cost)
+            .map(|(range, chunk)| {
+                assert_eq!(range.len(), chunk.len());
+                range
+            })
+            .collect()
+            .await
+    }
+
+    #[tokio::test]
+    async fn chunks_by_item_count() {
+        let chunking = ChunkingStrategy::Items(3);
+        let input = items(&[30; 7]);
+        let expected = vec![0..3, 3..6, 6..7];
+        assert_eq!(chunking.ranges(&input, FrameCost::MSET), expected);
+        assert_eq!(
+            stream_ranges(chunking, input, FrameCost::MSET).await,
+            expected
+        );
+    }
+
+    #[tokio::test]
+    async fn chunks_by_bytes() {
+        let chunking = ChunkingStrategy::Bytes(90);
+        let input = items(&[30, 30, 30, 40, 40, 25]);
+        let expected = vec![0..3, 3..5, 5..6];
+        assert_eq!(chunking.ranges(&input, FrameCost::default()), expected);
+        assert_eq!(
+            stream_ranges(chunking, input, FrameCost::default()).await,
+            expected
+        );
+    }
+
+    #[tokio::test]
+    async fn stops_at_whichever_limit_comes_first() {
+        let chunking = ChunkingStrategy::ItemsAndBytes {
+            max_items: 2,
+            max_bytes: 70,
+        };
+        let input = items(&[30, 30, 30, 60, 30, 30]);
+        let expected = vec![0..2, 2..3, 3..4, 4..6];
+        assert_eq!(chunking.ranges(&input, FrameCost::default()), expected);
+        assert_eq!(
+            stream_ranges(chunking, input, FrameCost::default()).await,
+            expected
+        );
+    }
+
+    #[tokio::test]
+    async fn sends_an_oversized_item_alone() {
+        let chunking = ChunkingStrategy::Bytes(70);
+        let input = items(&[30, 90, 30, 30]);
+        let expected = vec![0..1, 1..2, 2..4];
+        assert_eq!(chunking.ranges(&input, FrameCost::default()), expected);
+        assert_eq!(
+            stream_ranges(chunking, input, FrameCost::default()).await,
+            expected
+        );
+    }
+
+    #[tokio::test]
+    async fn counts_the_frame_cost_against_the_budget() {
+        let chunking = ChunkingStrategy::Bytes(150);
+        let input = items(&[30; 6]);
+        assert_eq!(
+            chunking.ranges(&input, FrameCost::default()),
+            vec![0..5, 5..6]
+        );
+        // 10 + 2 * (30 + 12 + 8) fits, a third item does not.
+        let cost = FrameCost {
+            per_chunk: 10,
+            per_item: 12,
+            repeats_key: true,
+        };
+        let expected = vec![0..2, 2..4, 4..6];
+        assert_eq!(chunking.ranges(&input, cost), expected);
+        assert_eq!(stream_ranges(chunking, input, cost).await, expected);
+    }
+
+    #[test]
+    fn charges_the_frame_cost_per_segment() {
+        let chunking = ChunkingStrategy::Bytes(150);
+        let input = items(&[30; 6]);
+        let cost = FrameCost {
+            per_chunk: 20,
+            ..FrameCost::default()
+        };
+        // One segment fits 20 + 4 * 30, two segments only 2 * 20 + 3 * 30.
+        assert_eq!(chunking.ranges(&input, cost), vec![0..4, 4..6]);
+        let segments = [0, 0, 1, 1, 1, 2];
+        assert_eq!(
+            chunking.segmented_ranges(&input, cost, |idx| segments[idx]),
+            vec![0..3, 3..6]
+        );
+    }
+
+    #[test]
+    fn measures_resp_lengths() {
+        assert_eq!(resp_bulk_len(0), "$0\r\n\r\n".len());
+        assert_eq!(resp_bulk_len(10), "$10\r\n0123456789\r\n".len());
+        assert_eq!(resp_array_header_len(3), "*3\r\n".len());
+        assert_eq!(
+            command_len("MSET", 4),
+            redis::cmd("MSET").get_packed_command().len() - "*1\r\n".len() + "*5\r\n".len()
+        );
+        assert_eq!(
+            args_resp_len(&"value"),
+            redis::cmd("SET").arg("value").get_packed_command().len()
+                - redis::cmd("SET").get_packed_command().len()
+        );
+    }
+}
@@ -0,0 +1,192 @@
+use crate::call_options::CallOptions;
+use crate::cancellation::ChunkGate;
+use crate::chunking::{ChunkItem, ChunkingStrategy, FrameCost};
+use crate::expiry::{Expiry, ExpiryMode, TtlJitter};
+use crate::metrics::{ClientMetrics, Metrics, instrument_read, instrument_write};
+use crate::redis_client::{AsyncRedisClient, PipelinedItemWrite, PipelinedWrite};
+use crate::retry::RetryPolicy;
+use crate::spans::{call_span, trace_read, trace_write};
+use crate::timeouts::Timeouts;
+use crate::write_outcome::WriteOutcome;
+use chrono::Utc;
+use redis::{Pipeline, RedisResult, ToRedisArgs};
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::TaskTracker;
+use tracing::Span;
+
+/// Settings that every client applies to its calls, set through `ConfigureClient`.
+#[derive(Clone)]
+pub struct ClientConfig {
+    pub(crate) chunking: ChunkingStrategy,
+    pub(crate) retry_policy: RetryPolicy,
+    pub(crate) expiry_mode: ExpiryMode,
+    pub(crate) ttl_jitter: Option<TtlJitter>,
+    pub(crate) transactions: bool,
+    pub(crate) timeouts: Timeouts,
+    /// Cancelled when the client shuts down.
+    pub(crate) shutdown: CancellationToken,
+    /// Calls in flight, which shutting down waits for.
+    pub(crate) calls: TaskTracker,
+    pub(crate) metrics: Option<ClientMetrics>,
+}
+
+impl ClientConfig {
+    /// Defaults of a new client: chunks of `batch_size` items, no retries, `ExpiryMode::ExAt`.
+    /// `timeouts` are those of the client's connections, applied to connections opened per call.
+    pub(crate) fn new(batch_size: usize, timeouts: Timeouts) -> Self {
+        Self {
+            chunking: ChunkingStrategy::Items(batch_size),
+            retry_policy: RetryPolicy::none(),
+            expiry_mode: ExpiryMode::default(),
+            ttl_jitter: None,
+            transactions: false,
+            timeouts,
+            shutdown: CancellationToken::new(),
+            calls: TaskTracker::new(),
+            metrics: None,
+        }
+    }
+
+    /// Gate of a call made with `call`.
+    pub(crate) fn gate(&self, call: &CallOptions) -> ChunkGate {
+        ChunkGate::new(call, &self.shutdown, &self.calls)
+    }
+
+    /// Span of a call of `items` items to `server`.
+    pub(crate) fn call_span(
+        &self,
+        method: &'static str,
+        server: &str,
+        items: usize,
+        ttl: Option<Duration>,
+    ) -> Span {
+        call_span(method, server, items, self.chunking.max_items(), ttl)
+    }
+
+    /// Pipeline of `kind` for `chunk`, with the client's expiry mode and jitter.
+    pub(crate) fn pipeline<K: ToRedisArgs + Sync + Send, V: ToRedisArgs + Sync + Send>(
+        &self,
+        kind: PipelinedWrite,
+        chunk: &[(K, V)],
+        ttl: Duration,
+    ) -> Pipeline {
+        kind.build(chunk, Utc::now(), ttl, self.expiry_mode, self.ttl_jitter)
+    }
+
+    /// Pipeline of `kind` for `chunk`, with the client's expiry mode and jitter.
+    pub(crate) fn item_pipeline<K: ToRedisArgs + Sync + Send, V: ToRedisArgs + Sync + Send>(
+        &self,
+        kind: PipelinedItemWrite,
+        chunk: &[(K, V, Expiry)],
+    ) -> Pipeline {
+        kind.build(chunk, Utc::now(), self.expiry_mode, self.ttl_jitter)
+    }
+
+    /// Frame cost of the chunks of `kind` with `ttl`, including the jitter and transactions.
+    pub(crate) fn frame_cost(&self, kind: PipelinedWrite, ttl: Duration) -> FrameCost {
+        self.transaction_cost(self.jittered_frame_cost(kind, ttl))
+    }
+
+    /// Frame cost of the chunks of `kind` with `ttl` that are always sent in a transaction, such
+    /// as compare-and-set chunks, whether or not the client uses transactions otherwise.
+    pub(crate) fn transaction_frame_cost(&self, kind: PipelinedWrite, ttl: Duration) -> FrameCost {
+        self.jittered_frame_cost(kind, ttl).with_transaction()
+    }
+
+    fn jittered_frame_cost(&self, kind: PipelinedWrite, ttl: Duration) -> FrameCost {
+        let jitter = self
+            .ttl_jitter
+            .map_or(Duration::ZERO, |jitter| jitter.max_offset(ttl));
+        kind.frame_cost(self.expiry_mode, ttl.saturating_add(jitter))
+    }
+
+    /// Frame cost of the chunks of `kind`, including the transactions.
+    pub(crate) fn item_frame_cost(&self, kind: PipelinedItemWrite) -> FrameCost {
+        self.transaction_cost(kind.frame_cost(self.expiry_mode))
+    }
+
+    /// `cost` with the `MULTI`/`EXEC` of a transaction when the client uses them.
+    pub(crate) fn transaction_cost(&self, cost: FrameCost) -> FrameCost {
+        match self.transactions {
+            true => cost.with_transaction(),
+            false => cost,
+        }
+    }
+
+    /// Runs the write call `method` of `items` inside `span` and records it in the metrics.
+    pub(crate) async fn write<T: ChunkItem>(
+        &self,
+        span: Span,
+        method: &'static str,
+        items: &[T],
+        write: impl Future<Output = WriteOutcome>,
+    ) -> WriteOutcome {
+        let write = instrument_write(self.metrics.as_ref(), method, items, write);
+        trace_write(span, write).await
+    }
+
+    /// Runs the read call `method` inside `span` and records it in the metrics.
+    pub(crate) async fn read<T>(
+        &self,
+        span: Span,
+        method: &'static str,
+        read: impl Future<Output = RedisResult<T>>,
+    ) -> RedisResult<T> {
+        trace_read(span, instrument_read(self.metrics.as_ref(), method, read)).await
+    }
+}
+
+/// Builder methods shared by the clients that talk to servers directly.
+pub trait ConfigureClient: AsyncRedisClient + Sized {
+    /// The settings the builder methods change.
+    fn config_mut(&mut self) -> &mut ClientConfig;
+
+    /// Sets the policy used to retry failed write chunks.
+    fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.config_mut().retry_policy = retry_policy;
+        self
+    }
+
+    /// Replaces the default `ChunkingStrategy::Items(batch_size)` chunking.
+    fn with_chunking(mut self, chunking: ChunkingStrategy) -> Self {
+        self.config_mut().chunking = chunking;
+        self
+    }
+
+    /// Sets how the `pipelined_*` methods express TTLs. Defaults to `ExpiryMode::ExAt`.
+    fn with_expiry_mode(mut self, expiry_mode: ExpiryMode) -> Self {
+        self.config_mut().expiry_mode = expiry_mode;
+        self
+    }
+
+    /// Adds `ttl_jitter` to the TTLs of the `pipelined_*` methods.
+    fn with_ttl_jitter(mut self, ttl_jitter: TtlJitter) -> Self {
+        self.config_mut().ttl_jitter = Some(ttl_jitter);
+        self
+    }
+
+    /// Wraps each chunk of the `pipelined_*` methods, and of the pooled client's `*_stream`
+    /// methods, in `MULTI`/`EXEC`, so that it applies entirely or not at all. The cluster client
+    /// opens one transaction per hash slot of a chunk, as a transaction may not span slots.
+    fn with_transactions(mut self, transactions: bool) -> Self {
+        self.config_mut().transactions = transactions;
+        self
+    }
+
+    /// Records the client's calls in `metrics`, labelled with `server_adder()`.
+    fn with_metrics(mut self, metrics: Metrics) -> Self {
+        let metrics = metrics.for_server(self.server_adder());
+        self.config_mut().metrics = Some(metrics);
+        self
+    }
+
+    /// Applies `configure` with `value` if there is one, e.g.
+    /// `client.with_some(ttl_jitter, ConfigureClient::with_ttl_jitter)`.
+    fn with_some<T>(self, value: Option<T>, configure: impl FnOnce(Self, T) -> Self) -> Self {
+        match value {
+            Some(value) => configure(self, value),
+            None => self,
+        }
+    }
+}
@@ -0,0 +1,780 @@
+use crate::call_options::CallOptions;
+use crate::chunking::{ChunkItem, FrameCost};
+use crate::client_config::{ClientConfig, ConfigureClient};
+use crate::expiry::Expiry;
+use crate::metrics::instrument_chunk;
+use crate::redis_client::{AsyncRedisClient, PipelinedItemWrite, PipelinedWrite};
+use crate::script::eval_fallback;
+use crate::spans::{call_span, record_chunks, record_connection, trace_chunk};
+use crate::timeouts::Timeouts;
+use crate::write_outcome::{WriteOutcome, index_runs};
+use futures::{StreamExt, TryStreamExt, stream};
+use redis::cluster::ClusterClient;
+use redis::cluster_async::ClusterConnection;
+use redis::cluster_routing::SingleNodeRoutingInfo;
+use redis::{
+    AsyncCommands, ConnectionAddr, ConnectionInfo, ErrorKind, FromRedisValue, Pipeline,
+    RedisResult, ToRedisArgs, Value,
+};
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use tracing::field::display;
+use tracing::{debug, warn};
+
+/// Number of hash slots in a Redis / Dragonfly cluster.
+pub const CLUSTER_SLOTS: u16 = 16384;
+
+/// Hash slot of `key`, honouring `{hash tags}` the same way the server does.
+pub fn key_slot(key: &[u8]) -> u16 {
+    let hashed = match key.iter().position(|b| *b == b'{') {
+        Some(open) => match key[open + 1..].iter().position(|b| *b == b'}') {
+            Some(len) if len > 0 => &key[open + 1..open + 1 + len],
+            _ => key,
+        },
+        None => key,
+    };
+    crc16::State::<crc16::XMODEM>::calculate(hashed) % CLUSTER_SLOTS
+}
+
+fn slot_of<K: ToRedisArgs>(key: &K) -> u16 {
+    let args = key.to_redis_args();
+    key_slot(args.first().map_or(&[][..], |arg| arg.as_slice()))
+}
+
+/// Borrows the `(key, value)` pairs of the items of one slot segment.
+fn pairs<'a, K, V>(items: &[&'a (K, V)]) -> Vec<(&'a K, &'a V)> {
+    items.iter().map(|(k, v)| (k, v)).collect()
+}
+
+/// Borrows the `(key, value, expiry)` items of one slot segment.
+fn triples<'a, K, V>(items: &[&'a (K, V, Expiry)]) -> Vec<(&'a K, &'a V, Expiry)> {
+    items.iter().map(|(k, v, expiry)| (k, v, *expiry)).collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct NodeAddr {
+    host: String,
+    port: u16,
+}
+
+/// Slot ranges and the primary that owns each, as reported by `CLUSTER SLOTS`.
+#[derive(Debug, Default)]
+struct SlotMap {
+    ranges: Vec<(Range<u16>, NodeAddr)>,
+}
+
+impl SlotMap {
+    fn parse(value: Value, default_host: &str) -> RedisResult<Self> {
+        let invalid = || redis::RedisError::from((ErrorKind::TypeError, "Invalid CLUSTER SLOTS"));
+        let Value::Array(entries) = value else {
+            return Err(invalid());
+        };
+
+        let mut ranges = Vec::with_capacity(entries.len());
+        for entry in entries {
+            let Value::Array(parts) = entry else {
+                return Err(invalid());
+            };
+            let (Some(start), Some(end), Some(Value::Array(primary))) =
+                (parts.first(), parts.get(1), parts.get(2))
+            else {
+                return Err(invalid());
+            };
+            let start = u16::from_redis_value(start)?;
+            let end = u16::from_redis_value(end)?;
+            let (Some(host), Some(port)) = (primary.first(), primary.get(1)) else {
+                return Err(invalid());
+            };
+            let host = String::from_redis_value(host)?;
+            // An empty or `?` endpoint means "the address you used to reach me".
+            let host = if host.is_empty() || host == "?" {
+                default_host.to_string()
+            } else {
+                host
+            };
+            let port = u16::from_redis_value(port)?;
+            ranges.push((start..end + 1, NodeAddr { host, port }));
+        }
+        ranges.sort_by_key(|(range, _)| range.start);
+        Ok(Self { ranges })
+    }
+
+    fn node_for_slot(&self, slot: u16) -> Option<&NodeAddr> {
+        let idx = self.ranges.partition_point(|(range, _)| range.end <= slot);
+        self.ranges
+            .get(idx)
+            .filter(|(range, _)| range.contains(&slot))
+            .map(|(_, node)| node)
+    }
+}
+
+/// A group of items sent to one node as a single pipeline. `segments` are ranges into `indices`
+/// that each stay within one hash slot, so multi-key commands never cross slots.
+struct NodeChunk {
+    node: NodeAddr,
+    indices: Vec<usize>,
+    segments: Vec<Range<usize>>,
+}
+
+impl NodeChunk {
+    fn ranges(&self) -> Vec<Range<usize>> {
+        let mut indices = self.indices.clone();
+        indices.sort_unstable();
+        index_runs(&indices)
+    }
+}
+
+/// `AsyncRedisClient` for Redis Cluster and Dragonfly in cluster mode.
+///
+/// Keys are grouped by the primary that owns their hash slot. Each group is chunked with the
+/// configured `ChunkingStrategy` and sent to that node as one pipeline, in which every `MSET`,
+/// `MGET` or per-key command batch stays within a single slot. Chunks for different nodes are
+/// interleaved and executed with up to `write_parallelism` in flight.
+pub struct AsyncRedisClientCluster {
+    pub nodes: Vec<ConnectionInfo>,
+    pub conn: ClusterConnection,
+    config: ClientConfig,
+    write_parallelism: usize,
+    slot_map: RwLock<Arc<SlotMap>>,
+    slot_map_stale: AtomicBool,
+}
+
+impl AsyncRedisClientCluster {
+    pub async fn new(
+        nodes: Vec<ConnectionInfo>,
+        batch_size: usize,
+        write_parallelism: usize,
+    ) -> RedisResult<Self> {
+        Self::new_with_timeouts(nodes, batch_size, write_parallelism, Timeouts::default()).await
+    }
+
+    /// Creates a client whose node connections apply the connect and response limits of
+    /// `timeouts`.
+    pub async fn new_with_timeouts(
+        nodes: Vec<ConnectionInfo>,
+        batch_size: usize,
+        write_parallelism: usize,
+        timeouts: Timeouts,
+    ) -> RedisResult<Self> {
+        let mut builder = ClusterClient::builder(nodes.clone());
+        if let Some(connect) = timeouts.connect {
+            builder = builder.connection_timeout(connect);
+        }
+        if let Some(response) = timeouts.response {
+            builder = builder.response_timeout(response);
+        }
+        let client = builder.build()?;
+        let conn = client.get_async_connection().await?;
+        let client = Self {
+            nodes,
+            conn,
+            config: ClientConfig::new(batch_size, timeouts),
+            write_parallelism: write_parallelism.max(1),
+            slot_map: RwLock::new(Arc::new(SlotMap::default())),
+            slot_map_stale: AtomicBool::new(true),
+        };
+        client.refresh_slot_map().await?;
+        Ok(client)
+    }
+
+    /// Reloads the slot to node mapping with `CLUSTER SLOTS`.
+    pub async fn refresh_slot_map(&self) -> RedisResult<()> {
+        let value: Value = redis::cmd("CLUSTER")
+            .arg("SLOTS")
+            .query_async(&mut self.conn.clone())
+            .await?;
+        let default_host = self
+            .nodes
+            .first()
+            .and_then(|info| match &info.addr {
+                ConnectionAddr::Tcp(host, _) | ConnectionAddr::TcpTls { host, .. } => {
+                    Some(host.clone())
+                }
+                _ => None,
+            })
+            .unwrap_or_default();
+        let slot_map = SlotMap::parse(value, &default_host)?;
+        debug!("Loaded {} cluster slot ranges", slot_map.ranges.len());
+        *self.slot_map.write().expect("slot map lock poisoned") = Arc::new(slot_map);
+        self.slot_map_stale.store(false, Ordering::SeqCst);
+        Ok(())
+    }
+
+    async fn current_slot_map(&self) -> RedisResult<Arc<SlotMap>> {
+        if self.slot_map_stale.load(Ordering::SeqCst) {
+            self.refresh_slot_map().await?;
+        }
+        Ok(self
+            .slot_map
+            .read()
+            .expect("slot map lock poisoned")
+            .clone())
+    }
+
+    /// Groups items by owning node and hash slot, then chunks each node's items. `chunk_ranges`
+    /// receives the item indices of one node, ordered by slot, and returns how to chunk them.
+    fn plan(
+        slot_map: &SlotMap,
+        slots: &[u16],
+        chunk_ranges: impl Fn(&[usize]) -> Vec<Range<usize>>,
+    ) -> RedisResult<Vec<NodeChunk>> {
+        let mut by_node: BTreeMap<&NodeAddr, BTreeMap<u16, Vec<usize>>> = BTreeMap::new();
+        for (idx, &slot) in slots.iter().enumerate() {
+            let node = slot_map.node_for_slot(slot).ok_or_else(|| {
+                redis::RedisError::from((
+                    ErrorKind::ClusterConnectionNotFound,
+                    "No node serves slot",
+                    slot.to_string(),
+                ))
+            })?;
+            by_node
+                .entry(node)
+                .or_default()
+                .entry(slot)
+                .or_default()
+                .push(idx);
+        }
+
+        let mut per_node: Vec<Vec<NodeChunk>> = Vec::with_capacity(by_node.len());
+        for (node, by_slot) in by_node {
+            let mut indices = Vec::new();
+            let mut slot_bounds = Vec::with_capacity(by_slot.len());
+            for (_, slot_indices) in by_slot {
+                let start = indices.len();
+                indices.extend(slot_indices);
+                slot_bounds.push(start..indices.len());
+            }
+
+            let chunks = chunk_ranges(&indices)
+                .into_iter()
+                .map(|range| {
+                    let segments = slot_bounds
+                        .iter()
+                        .filter_map(|slot| {
+                            let start = slot.start.max(range.start);
+                            let end = slot.end.min(range.end);
+                            (start < end).then(|| start - range.start..end - range.start)
+                        })
+                        .collect();
+                    NodeChunk {
+                        node: node.clone(),
+                        indices: indices[range].to_vec(),
+                        segments,
+                    }
+                })
+                .collect();
+            per_node.push(chunks);
+        }
+
+        // Interleave the nodes so that the chunks in flight are spread across the cluster.
+        let mut chunks = Vec::with_capacity(per_node.iter().map(Vec::len).sum());
+        let mut iters: Vec<_> = per_node.into_iter().map(Vec::into_iter).collect();
+        while !iters.is_empty() {
+            iters.retain_mut(|iter| match iter.next() {
+                Some(chunk) => {
+                    chunks.push(chunk);
+                    true
+                }
+                None => false,
+            });
+        }
+        Ok(chunks)
+    }
+
+    async fn route_pipeline(
+        &self,
+        node: &NodeAddr,
+        pipeline: &Pipeline,
+    ) -> RedisResult<Vec<Value>> {
+        record_connection(display(format!("{}:{}", node.host, node.port)));
+        let route = SingleNodeRoutingInfo::ByAddress {
+            host: node.host.clone(),
+            port: node.port,
+        };
+        let mut conn = self.conn.clone();
+        let values = conn
+            .route_pipeline(pipeline, 0, pipeline.len(), route.clone())
+            .await?;
+        match values.into_iter().map(Value::extract_error).collect() {
+            Err(err) if err.kind() == ErrorKind::NoScriptError => {
+                debug!(
+                    "Script not loaded on {}:{}, falling back to EVAL",
+                    node.host, node.port
+                );
+                let fallback = eval_fallback(pipeline);
+                let values = conn
+                    .route_pipeline(&fallback, 0, fallback.len(), route)
+                    .await?;
+                values.into_iter().map(Value::extract_error).collect()
+            }
+            result => result,
+        }
+    }
+
+    async fn execute_pipelines<T: ChunkItem + Sync>(
+        &self,
+        items: &[T],
+        cost: FrameCost,
+        build_pipeline: impl Fn(&[&T]) -> Pipeline,
+        method: &'static str,
+        context: &'static str,
+        call: &CallOptions,
+    ) -> WriteOutcome {
+        let gate = &self.config.gate(call);
+        let mut outcome = WriteOutcome::default();
+        if items.is_empty() {
+            return outcome;
+        }
+
+        let slots: Vec<u16> = items.iter().map(|item| slot_of(item.key())).collect();
+        let plan = match self.current_slot_map().await {
+            Ok(slot_map) => Self::plan(&slot_map, &slots, |indices| {
+                let refs: Vec<&T> = indices.iter().map(|&i| &items[i]).collect();
+                // Each hash slot of a chunk is sent as commands, and a transaction, of its own.
+                self.config
+                    .chunking
+                    .segmented_ranges(&refs, cost, |idx| slots[indices[idx]])
+            }),
+            Err(err) => Err(err),
+        };
+        let chunks = match plan {
+            Ok(chunks) => chunks,
+            Err(err) => {
+                warn!(
+                    "Failed to plan {} for {} features: {}",
+                    context,
+                    items.len(),
+                    err
+                );
+                self.slot_map_stale.store(true, Ordering::SeqCst);
+                outcome.record(0..items.len(), Err(err));
+                return outcome.finish();
+            }
+        };
+        record_chunks(chunks.len());
+
+        let pipelines: Vec<(NodeChunk, Pipeline)> = chunks
+            .into_iter()
+            .map(|chunk| {
+                let mut pipeline = redis::pipe();
+                for segment in &chunk.segments {
+                    let refs: Vec<&T> = chunk.indices[segment.clone()]
+                        .iter()
+                        .map(|&i| &items[i])
+                        .collect();
+                    // `Pipeline::atomic` would wrap the whole node chunk, and a transaction may
+                    // not span hash slots.
+                    if self.config.transactions {
+                        pipeline.cmd("MULTI");
+                    }
+                    for cmd in build_pipeline(&refs).cmd_iter() {
+                        pipeline.add_command(cmd.clone());
+                    }
+                    if self.config.transactions {
+                        pipeline.cmd("EXEC");
+                    }
+                }
+                (chunk, pipeline)
+            })
+            .collect();
+
+        let mut tasks = stream::iter(pipelines.into_iter().enumerate().map(
+            |(index, (chunk, pipeline))| async move {
+                if let Some(stop) = gate.closed() {
+                    return (chunk, Instant::now(), Err(stop));
+                }
+                let started = Instant::now();
+                let result = instrument_chunk(
+                    self.config.metrics.as_ref(),
+                    method,
+                    context,
+                    trace_chunk(
+                        index,
+                        chunk.indices.len(),
+                        self.config
+                            .retry_policy
+                            .retry_until(context, gate.deadline(), || {
+                                self.route_pipeline(&chunk.node, &pipeline)
+                            }),
+                    ),
+                )
+                .await
+                .map(|_| ());
+                (chunk, started, Ok(result))
+            },
+        ))
+        .buffer_unordered(self.write_parallelism);
+
+        while let Some((chunk, started, result)) = tasks.next().await {
+            let result = match result {
+                Ok(result) => result,
+                Err(stop) => {
+                    for range in chunk.ranges() {
+                        outcome.record_stopped(range, stop);
+                    }
+                    continue;
+                }
+            };
+            match &result {
+                Ok(()) => debug!(
+                    "Executed {} pipeline with {} items on {}:{} in {} ms",
+                    context,
+                    chunk.indices.len(),
+                    chunk.node.host,
+                    chunk.node.port,
+                    started.elapsed().as_millis()
+                ),
+                Err(err) => {
+                    warn!(
+                        "Failed to sync {} features via {} on {}:{}: {}",
+                        chunk.indices.len(),
+                        context,
+                        chunk.node.host,
+                        chunk.node.port,
+                        err
+                    );
+                    self.slot_map_stale.store(true, Ordering::SeqCst);
+                }
+            }
+            outcome.record_ranges(chunk.ranges(), result);
+        }
+
+        outcome.finish()
+    }
+
+    async fn cluster_multi_get<K: ToRedisArgs + Sync + Send, V: FromRedisValue + Send>(
+        &self,
+        keys: &[K],
+        call: &CallOptions,
+    ) -> RedisResult<Vec<Option<V>>> {
+        let gate = &self.config.gate(call);
+        if keys.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let slots: Vec<u16> = keys.iter().map(slot_of).collect();
+        let slot_map = self.current_slot_map().await?;
+        let chunks = Self::plan(&slot_map, &slots, |indices| {
+            let refs: Vec<&K> = indices.iter().map(|&i| &keys[i]).collect();
+            self.config.chunking.key_ranges(&refs)
+        })?;
+        record_chunks(chunks.len());
+        // Keys of each chunk and the chunks after it, which are started in order.
+        let mut unsent: Vec<usize> = chunks
+            .iter()
+            .rev()
+            .scan(0, |keys, chunk| {
+                *keys += chunk.indices.len();
+                Some(*keys)
+            })
+            .collect();
+        unsent.reverse();
+        let unsent = &unsent;
+
+        let chunk_values: Vec<(NodeChunk, Vec<Value>)> = stream::iter(
+            chunks
+                .into_iter()
+                .enumerate()
+                .map(|(index, chunk)| async move {
+                    if let Some(stop) = gate.closed() {
+                        return Err(stop.error(unsent[index]));
+                    }
+                    let mut pipeline = redis::pipe();
+                    for segment in &chunk.segments {
+                        let cmd = pipeline.cmd("MGET");
+                        for &i in &chunk.indices[segment.clone()] {
+                            cmd.arg(&keys[i]);
+                        }
+                    }
+                    let values = instrument_chunk(
+                        self.config.metrics.as_ref(),
+                        "multi_get",
+                        "mget",
+                        trace_chunk(
+                            index,
+                            chunk.indices.len(),
+                            self.route_pipeline(&chunk.node, &pipeline),
+                        ),
+                    )
+                    .await;
+                    if values.is_err() {
+                        self.slot_map_stale.store(true, Ordering::SeqCst);
+                    }
+                    values.map(|values| (chunk, values))
+                }),
+        )
+        .buffer_unordered(self.write_parallelism)
+        .try_collect()
+        .await?;
+
+        let mut result: Vec<Option<V>> = (0..keys.len()).map(|_| None).collect();
+        for (chunk, values) in chunk_values {
+            for (segment, value) in chunk.segments.iter().zip(values) {
+                let segment_values: Vec<Option<V>> = FromRedisValue::from_owned_redis_value(value)?;
+                for (&i, value) in chunk.indices[segment.clone()].iter().zip(segment_values) {
+                    result[i] = value;
+                }
+            }
+        }
+        Ok(result)
+    }
+}
+
+impl ConfigureClient for AsyncRedisClientCluster {
+    fn config_mut(&mut self) -> &mut ClientConfig {
+        &mut self.config
+    }
+}
+
+impl AsyncRedisClient for AsyncRedisClientCluster {
+    async fn ping(&self) -> RedisResult<String> {
+        let span = call_span("ping", &self.server_adder(), 0, None, None);
+        let ping = async { self.conn.clone().ping().await };
+        self.config.read(span, "ping", ping).await
+    }
+
+    async fn multi_get<K: ToRedisArgs + Sync + Send, V: FromRedisValue + Send>(
+        &self,
+        keys: &[K],
+        call: &CallOptions,
+    ) -> RedisResult<Vec<Option<V>>> {
+        let span = self
+            .config
+            .call_span("multi_get", &self.server_adder(), keys.len(), None);
+        let read = self.cluster_multi_get(keys, call);
+        self.config.read(span, "multi_get", read).await
+    }
+
+    async fn multi_set<K: ToRedisArgs + Sync + Send, V: ToRedisArgs + Sync + Send>(
+        &self,
+        items: &[(K, V)],
+        call: &CallOptions,
+    ) -> WriteOutcome {
+        let span = self
+            .config
+            .call_span("multi_set", &self.server_adder(), items.len(), None);
+        let write = self.execute_pipelines(
+            items,
+            self.config.transaction_cost(FrameCost::MSET),
+            |chunk| {
+                let mut pipeline = redis::pipe();
+                pipeline.mset(&pairs(chunk));
+                pipeline
+            },
+            "multi_set",
+            "mset",
+            call,
+        );
+        self.config.write(span, "multi_set", items, write).await
+    }
+
+    async fn pipelined_write<K: ToRedisArgs + Sync + Send, V: ToRedisArgs + Sync + Send>(
+        &self,
+        kind: PipelinedWrite,
+        items: &[(K, V)],
+        ttl: Duration,
+        call: &CallOptions,
+    ) -> WriteOutcome {
+        let span =
+            self.config
+                .call_span(kind.method(), &self.server_adder(), items.len(), Some(ttl));
+        let write = self.execute_pipelines(
+            items,
+            self.config.frame_cost(kind, ttl),
+            |chunk| self.config.pipeline(kind, &pairs(chunk), ttl),
+            kind.method(),
+            kind.context(),
+            call,
+        );
+        self.config.write(span, kind.method(), items, write).await
+    }
+
+    async fn pipelined_item_write<K: ToRedisArgs + Sync + Send, V: ToRedisArgs + Sync + Send>(
+        &self,
+        kind: PipelinedItemWrite,
+        items: &[(K, V, Expiry)],
+        call: &CallOptions,
+    ) -> WriteOutcome {
+        let span = self
+            .config
+            .call_span(kind.method(), &self.server_adder(), items.len(), None);
+        let write = self.execute_pipelines(
+            items,
+            self.config.item_frame_cost(kind),
+            |chunk| self.config.item_pipeline(kind, &triples(chunk)),
+            kind.method(),
+            kind.context(),
+            call,
+        );
+        self.config.write(span, kind.method(), items, write).await
+    }
+
+    fn server_adder(&self) -> String {
+        self.nodes
+            .iter()
+            .map(|info| info.addr.to_string())
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunking::ChunkingStrategy;
+
+    fn node(port: u16) -> NodeAddr {
+        NodeAddr {
+            host: "10.0.0.1".to_string(),
+            port,
+        }
+    }
+
+    fn slots_entry(start: i64, end: i64, host: &str, port: i64) -> Value {
+        Value::Array(vec![
+            Value::Int(start),
+            Value::Int(end),
+            Value::Array(vec![
+                Value::BulkString(host.as_bytes().to_vec()),
+                Value::Int(port),
+                Value::BulkString(b"node-id".to_vec()),
+            ]),
+        ])
+    }
+
+    fn three_nodes() -> SlotMap {
+        SlotMap::parse(
+            Value::Array(vec![
+                slots_entry(10923, 16383, "10.0.0.1", 7002),
+                slots_entry(0, 5460, "10.0.0.1", 7000),
+                slots_entry(5461, 10922, "10.0.0.1", 7001),
+            ]),
+            "10.0.0.1",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn key_slot_matches_the_server() {
+        assert_eq!(key_slot(b"123456789"), 12739);
+        assert_eq!(key_slot(b"foo"), 12182);
+        assert_eq!(key_slot(b""), 0);
+    }
+
+    #[test]
+    fn key_slot_honours_hash_tags() {
+        assert_eq!(key_slot(b"{user1000}.following"), key_slot(b"user1000"));
+        assert_eq!(key_slot(b"{user1000}.followers"), key_slot(b"user1000"));
+        assert_eq!(key_slot(b"foo{bar}{zap}"), key_slot(b"bar"));
+        // An empty or unterminated tag hashes the whole key.
+        assert_eq!(
+            key_slot(b"foo{}{bar}"),
+            crc16::State::<crc16::XMODEM>::calculate(b"foo{}{bar}") % CLUSTER_SLOTS
+        );
+        assert_eq!(
+            key_slot(b"foo{bar"),
+            crc16::State::<crc16::XMODEM>::calculate(b"foo{bar") % CLUSTER_SLOTS
+        );
+    }
+
+    #[test]
+    fn slot_map_covers_every_slot() {
+        let map = three_nodes();
+        assert_eq!(map.node_for_slot(0), Some(&node(7000)));
+        assert_eq!(map.node_for_slot(5460), Some(&node(7000)));
+        assert_eq!(map.node_for_slot(5461), Some(&node(7001)));
+        assert_eq!(map.node_for_slot(10923), Some(&node(7002)));
+        assert_eq!(map.node_for_slot(CLUSTER_SLOTS - 1), Some(&node(7002)));
+        assert_eq!(map.node_for_slot(CLUSTER_SLOTS), None);
+    }
+
+    #[test]
+    fn slot_map_leaves_gaps_unassigned() {
+        let map =
+            SlotMap::parse(Value::Array(vec![slots_entry(0, 99, "", 7000)]), "10.0.0.1").unwrap();
+        assert_eq!(map.node_for_slot(99), Some(&node(7000)));
+        assert_eq!(map.node_for_slot(100), None);
+    }
+
+    #[test]
+    fn slot_map_resolves_unknown_endpoints() {
+        let map = SlotMap::parse(
+            Value::Array(vec![slots_entry(0, 16383, "?", 7000)]),
+            "10.0.0.1",
+        )
+        .unwrap();
+        assert_eq!(map.node_for_slot(42), Some(&node(7000)));
+    }
+
+    #[test]
+    fn slot_map_rejects_malformed_replies() {
+        assert!(SlotMap::parse(Value::Nil, "h").is_err());
+        assert!(SlotMap::parse(Value::Array(vec![Value::Int(0)]), "h").is_err());
+        let no_primary = Value::Array(vec![Value::Array(vec![Value::Int(0), Value::Int(1)])]);
+        assert!(SlotMap::parse(no_primary, "h").is_err());
+    }
+
+    #[test]
+    fn plan_segments_chunks_by_slot() {
+        let map = three_nodes();
+        // Three slots on node 7000 (1, 2, 3) and one on node 7001.
+        let slots = [2, 1, 6000, 2, 3, 1];
+        let chunks = AsyncRedisClientCluster::plan(&map, &slots, |indices| {
+            ChunkingStrategy::Items(3).key_ranges(indices)
+        })
+        .unwrap();
+
+        let first = &chunks[0];
+        assert_eq!(first.node, node(7000));
+        // Ordered by slot: slot 1 holds items 1 and 5, slot 2 items 0 and 3, slot 3 item 4.
+        assert_eq!(first.indices, vec![1, 5, 0]);
+        assert_eq!(first.segments, vec![0..2, 2..3]);
+
+        // Nodes are interleaved, so node 7001's single chunk comes second.
+        assert_eq!(chunks[1].node, node(7001));
+        assert_eq!(chunks[1].indices, vec![2]);
+        assert_eq!(chunks[1].segments, vec![0..1]);
+
+        assert_eq!(chunks[2].node, node(7000));
+        assert_eq!(chunks[2].indices, vec![3, 4]);
+        assert_eq!(chunks[2].segments, vec![0..1, 1..2]);
+        assert_eq!(chunks.len(), 3);
+    }
+
+    #[test]
+    fn plan_charges_each_slot_of_a_chunk_its_own_frames() {
+        let map = three_nodes();
+        // Eight slots, all on node 7000, with one item each.
+        let slots: Vec<u16> = (1..=8).collect();
+        let items = vec![("key", "value"); slots.len()];
+        let cost = FrameCost::MSET.with_transaction();
+        let segment_bytes = cost.per_chunk + cost.item_len(&items[0]);
+        let chunking = ChunkingStrategy::Bytes(2 * segment_bytes);
+        let chunks = AsyncRedisClientCluster::plan(&map, &slots, |indices| {
+            let refs: Vec<_> = indices.iter().map(|&i| &items[i]).collect();
+            chunking.segmented_ranges(&refs, cost, |idx| slots[indices[idx]])
+        })
+        .unwrap();
+
+        assert_eq!(chunks.len(), 4);
+        for chunk in &chunks {
+            assert_eq!(chunk.node, node(7000));
+            assert_eq!(chunk.segments, vec![0..1, 1..2]);
+        }
+    }
+
+    #[test]
+    fn plan_fails_for_unserved_slots() {
+        let map =
+            SlotMap::parse(Value::Array(vec![slots_entry(0, 99, "", 7000)]), "10.0.0.1").unwrap();
+        let err = AsyncRedisClientCluster::plan(&map, &[5, 200], |indices| {
+            ChunkingStrategy::Items(3).key_ranges(indices)
+        })
+        .err()
+        .unwrap();
+        assert_eq!(err.kind(), ErrorKind::ClusterConnectionNotFound);
+    }
+}
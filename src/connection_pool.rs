@@ -0,0 +1,784 @@
+use crate::metrics::ClientMetrics;
+use crate::spans::record_connection;
+use crate::timeouts::{Timeouts, acquire_error};
+use futures::future::{BoxFuture, select_all};
+use rand::Rng;
+use redis::aio::MultiplexedConnection;
+use redis::{AsyncConnectionConfig, ErrorKind, RedisError, RedisResult};
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock, Weak};
+use std::time::{Duration, Instant};
+use tokio::sync::{Mutex, Semaphore, SemaphorePermit};
+use tracing::{debug, warn};
+
+/// Errors that mean the socket itself is unusable and the connection has to be replaced.
+pub fn is_connection_error(err: &RedisError) -> bool {
+    err.is_io_error()
+        || err.is_connection_dropped()
+        || err.is_connection_refusal()
+        || err.is_unrecoverable_error()
+}
+
+fn closed_error() -> RedisError {
+    RedisError::from((ErrorKind::ClientError, "Connection pool is closed"))
+}
+
+/// Background probing of pooled connections.
+///
+/// Every `interval` each connection that has not been used for at least `idle_after` and is not
+/// busy is sent a `PING`. Connections that fail it or do not answer within `ping_timeout` are
+/// evicted and reconnected on their next use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HealthCheck {
+    pub interval: Duration,
+    pub idle_after: Duration,
+    pub ping_timeout: Duration,
+}
+
+impl Default for HealthCheck {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(5),
+            idle_after: Duration::from_secs(5),
+            ping_timeout: Duration::from_secs(1),
+        }
+    }
+}
+
+/// How a pool picks the connection for the next call.
+///
+/// Whatever the strategy, a caller that finds all of its candidates busy waits for the first of
+/// them to free up rather than for a fixed one.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SelectionStrategy {
+    /// Try connections in turn, starting one further along for every call.
+    #[default]
+    RoundRobin,
+    /// Prefer the connection with the fewest calls in flight.
+    LeastInFlight,
+    /// Pick two connections at random and prefer the one with fewer calls in flight.
+    PowerOfTwoChoices,
+    /// Serve callers strictly in arrival order once the pool is saturated.
+    FairQueue,
+}
+
+/// Shape of a `ConnectionPool`.
+///
+/// The pool opens `min_size` connections up front and grows towards `max_size` when every open
+/// connection is busy. With an `idle_timeout`, connections above `min_size` that have not been
+/// used for that long are closed again. `timeouts` limit connecting, responses and the wait for a
+/// free connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolOptions {
+    pub min_size: usize,
+    pub max_size: usize,
+    pub idle_timeout: Option<Duration>,
+    pub selection: SelectionStrategy,
+    /// Calls that may share a connection at the same time. `1` gives each caller exclusive use.
+    pub max_in_flight_per_connection: usize,
+    pub timeouts: Timeouts,
+}
+
+impl PoolOptions {
+    /// A pool of exactly `size` connections.
+    pub fn with_size(size: usize) -> Self {
+        Self::with_bounds(size, size)
+    }
+
+    /// A pool that keeps at least `min_size` and at most `max_size` connections open.
+    pub fn with_bounds(min_size: usize, max_size: usize) -> Self {
+        Self {
+            min_size,
+            max_size,
+            idle_timeout: None,
+            selection: SelectionStrategy::default(),
+            max_in_flight_per_connection: 1,
+            timeouts: Timeouts::default(),
+        }
+    }
+
+    pub fn with_idle_timeout(mut self, idle_timeout: Duration) -> Self {
+        self.idle_timeout = Some(idle_timeout);
+        self
+    }
+
+    pub fn with_selection(mut self, selection: SelectionStrategy) -> Self {
+        self.selection = selection;
+        self
+    }
+
+    pub fn with_max_in_flight_per_connection(mut self, max_in_flight: usize) -> Self {
+        self.max_in_flight_per_connection = max_in_flight;
+        self
+    }
+
+    pub fn with_timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
+}
+
+struct Slot {
+    /// `None` once the connection has been evicted; the next caller reconnects.
+    connection: Option<MultiplexedConnection>,
+    /// Bumped on every reconnect so that a late failure does not evict the replacement.
+    generation: u64,
+}
+
+struct PooledConnection {
+    slot: Mutex<Slot>,
+    /// One permit per call that may use the connection concurrently.
+    permits: Semaphore,
+    /// Milliseconds since `ConnectionPool::epoch` at which the connection was last used.
+    last_used_ms: AtomicU64,
+}
+
+/// A checked-out connection. Dropping it releases the connection for the next caller.
+struct Lease<'a> {
+    idx: usize,
+    _permit: SemaphorePermit<'a>,
+}
+
+/// A set of multiplexed connections, each shared by at most `max_in_flight_per_connection`
+/// callers, handed out according to a `SelectionStrategy`.
+///
+/// Slots for `max_size` connections are allocated up front; the first `active` of them are in
+/// rotation. Growing activates the next slot and connects it on first use, shrinking retires the
+/// last one once it has been idle for `idle_timeout`.
+///
+/// Connections that fail with a connection-level error (see `is_connection_error`), either while
+/// serving a caller or during a health check, are evicted and lazily re-established. Once the pool
+/// is closed, no connection is re-established and callers fail instead.
+pub(crate) struct ConnectionPool {
+    client: redis::Client,
+    config: AsyncConnectionConfig,
+    acquire_timeout: Option<Duration>,
+    connections: Vec<PooledConnection>,
+    active: AtomicUsize,
+    min_size: usize,
+    selection: SelectionStrategy,
+    max_in_flight: usize,
+    /// Held by a `SelectionStrategy::FairQueue` caller while it picks, and if need be waits for, a
+    /// connection. The lock is FIFO, so only the longest waiting caller competes for the next
+    /// free connection.
+    turn: Mutex<()>,
+    next_id: AtomicUsize,
+    epoch: Instant,
+    closed: AtomicBool,
+    /// Metrics of the owning client and the `pool` label to record waits under.
+    metrics: OnceLock<(ClientMetrics, &'static str)>,
+}
+
+impl ConnectionPool {
+    pub(crate) async fn new(
+        client: &redis::Client,
+        options: PoolOptions,
+    ) -> RedisResult<Arc<Self>> {
+        let max_size = options.max_size.max(1);
+        let min_size = options.min_size.clamp(1, max_size);
+        let max_in_flight = options.max_in_flight_per_connection.max(1);
+        let config = options.timeouts.connection_config();
+        let mut connections = Vec::with_capacity(max_size);
+        for idx in 0..max_size {
+            let connection = if idx < min_size {
+                Some(
+                    client
+                        .get_multiplexed_async_connection_with_config(&config)
+                        .await?,
+                )
+            } else {
+                None
+            };
+            connections.push(PooledConnection {
+                slot: Mutex::new(Slot {
+                    connection,
+                    generation: 0,
+                }),
+                permits: Semaphore::new(max_in_flight),
+                last_used_ms: AtomicU64::new(0),
+            });
+        }
+        let pool = Arc::new(Self {
+            client: client.clone(),
+            config,
+            acquire_timeout: options.timeouts.acquire,
+            connections,
+            active: AtomicUsize::new(min_size),
+            min_size,
+            selection: options.selection,
+            max_in_flight,
+            turn: Mutex::new(()),
+            next_id: AtomicUsize::new(0),
+            epoch: Instant::now(),
+            closed: AtomicBool::new(false),
+            metrics: OnceLock::new(),
+        });
+        if let Some(idle_timeout) = options.idle_timeout
+            && min_size < max_size
+        {
+            pool.spawn_idle_reaper(idle_timeout);
+        }
+        Ok(pool)
+    }
+
+    /// Number of calls the pool can serve at once when fully grown.
+    pub(crate) fn capacity(&self) -> usize {
+        self.connections.len() * self.max_in_flight
+    }
+
+    /// Number of connections currently in rotation.
+    fn active_size(&self) -> usize {
+        self.active.load(Ordering::Acquire)
+    }
+
+    /// Number of calls currently using the connection at `idx`.
+    fn in_flight_at(&self, idx: usize) -> usize {
+        self.max_in_flight - self.connections[idx].permits.available_permits()
+    }
+
+    /// Records the time callers wait for a connection in `metrics`, labelled with `pool`.
+    /// Only the first call has an effect.
+    pub(crate) fn set_metrics(&self, metrics: ClientMetrics, pool: &'static str) {
+        let _ = self.metrics.set((metrics, pool));
+    }
+
+    /// Runs `execute_fn` on the next connection; `method` labels the recorded pool wait. Fails
+    /// without running it if no connection frees up within the acquire timeout.
+    pub(crate) async fn with_next_connection<T, F>(
+        &self,
+        method: &'static str,
+        execute_fn: F,
+    ) -> RedisResult<T>
+    where
+        for<'a> F: FnOnce(&'a mut MultiplexedConnection) -> BoxFuture<'a, RedisResult<T>>,
+    {
+        let started = Instant::now();
+        let lease = match self.acquire_timeout {
+            Some(limit) => tokio::time::timeout(limit, self.acquire()).await.ok(),
+            None => Some(self.acquire().await),
+        };
+        if let Some((metrics, pool)) = self.metrics.get() {
+            metrics.observe_pool_wait(pool, method, started.elapsed());
+        }
+        let lease = lease.ok_or_else(|| acquire_error(started.elapsed()))?;
+        record_connection(lease.idx);
+        let (mut connection, generation) = self.checkout(lease.idx).await?;
+        let result = execute_fn(&mut connection).await;
+        self.touch(lease.idx);
+        if let Err(err) = &result
+            && is_connection_error(err)
+        {
+            self.evict(lease.idx, generation, err).await;
+        }
+        result
+    }
+
+    async fn acquire(&self) -> Lease<'_> {
+        let _turn = match self.selection {
+            SelectionStrategy::FairQueue => Some(self.turn.lock().await),
+            _ => None,
+        };
+        loop {
+            let (idx, permit) = self.acquire_permit().await;
+            // The idle reaper may have retired the slot between picking it and getting its permit.
+            // It lowers `active` before releasing the slot's permits, so this check is enough.
+            if idx < self.active_size() {
+                return Lease {
+                    idx,
+                    _permit: permit,
+                };
+            }
+        }
+    }
+
+    async fn acquire_permit(&self) -> (usize, SemaphorePermit<'_>) {
+        let candidates = self.candidates();
+        for &idx in &candidates {
+            if let Ok(permit) = self.connections[idx].permits.try_acquire() {
+                return (idx, permit);
+            }
+        }
+        if let Some(grown) = self.grow() {
+            return grown;
+        }
+
+        let waits = candidates.into_iter().map(|idx| {
+            Box::pin(async move {
+                let permit = self.connections[idx]
+                    .permits
+                    .acquire()
+                    .await
+                    .expect("connection permits are never closed");
+                (idx, permit)
+            })
+        });
+        select_all(waits).await.0
+    }
+
+    /// Brings the next slot into rotation, unless the pool is at `max_size`. The slot connects on
+    /// first use.
+    fn grow(&self) -> Option<(usize, SemaphorePermit<'_>)> {
+        let mut active = self.active_size();
+        while active < self.connections.len() {
+            match self.active.compare_exchange(
+                active,
+                active + 1,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => {
+                    debug!("Growing pool to {} connections", active + 1);
+                    return self.connections[active]
+                        .permits
+                        .try_acquire()
+                        .ok()
+                        .map(|permit| (active, permit));
+                }
+                Err(current) => active = current,
+            }
+        }
+        None
+    }
+
+    /// Connections to try, most preferred first.
+    fn candidates(&self) -> Vec<usize> {
+        let pool_size = self.active_size();
+        let start = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let rotation = (0..pool_size).map(|offset| (start + offset) % pool_size);
+        match self.selection {
+            SelectionStrategy::RoundRobin | SelectionStrategy::FairQueue => rotation.collect(),
+            SelectionStrategy::LeastInFlight => {
+                let mut candidates: Vec<usize> = rotation.collect();
+                candidates.sort_by_key(|&idx| self.in_flight_at(idx));
+                candidates
+            }
+            SelectionStrategy::PowerOfTwoChoices if pool_size > 1 => {
+                let mut rng = rand::rng();
+                let first = rng.random_range(0..pool_size);
+                let second = (first + rng.random_range(1..pool_size)) % pool_size;
+                let mut candidates = vec![first, second];
+                candidates.sort_by_key(|&idx| self.in_flight_at(idx));
+                candidates
+            }
+            SelectionStrategy::PowerOfTwoChoices => vec![0],
+        }
+    }
+
+    /// Returns a handle to the connection at `idx`, reconnecting it if it was evicted.
+    async fn checkout(&self, idx: usize) -> RedisResult<(MultiplexedConnection, u64)> {
+        if self.closed.load(Ordering::Acquire) {
+            return Err(closed_error());
+        }
+        let mut slot = self.connections[idx].slot.lock().await;
+        if let Some(connection) = &slot.connection {
+            return Ok((connection.clone(), slot.generation));
+        }
+        debug!("Opening pooled connection {}", idx);
+        let connection = self
+            .client
+            .get_multiplexed_async_connection_with_config(&self.config)
+            .await?;
+        slot.generation += 1;
+        slot.connection = Some(connection.clone());
+        Ok((connection, slot.generation))
+    }
+
+    async fn evict(&self, idx: usize, generation: u64, err: &RedisError) {
+        let mut slot = self.connections[idx].slot.lock().await;
+        if slot.generation == generation && slot.connection.is_some() {
+            warn!("Evicting pooled connection {}: {}", idx, err);
+            slot.connection = None;
+        }
+    }
+
+    /// Closes the pool: waits for the calls using each connection to finish, then drops the
+    /// connection. Calls that check out a connection afterwards fail.
+    pub(crate) async fn close(&self) {
+        self.closed.store(true, Ordering::Release);
+        for (idx, pooled) in self.connections.iter().enumerate() {
+            let _permits = pooled
+                .permits
+                .acquire_many(self.max_in_flight as u32)
+                .await
+                .expect("connection permits are never closed");
+            if pooled.slot.lock().await.connection.take().is_some() {
+                debug!("Closed pooled connection {}", idx);
+            }
+        }
+    }
+
+    fn touch(&self, idx: usize) {
+        let now_ms = self.epoch.elapsed().as_millis() as u64;
+        self.connections[idx]
+            .last_used_ms
+            .store(now_ms, Ordering::Relaxed);
+    }
+
+    /// Retires the last active connection while it has been idle for `idle_timeout` and the pool
+    /// is above `min_size`, until the pool is dropped.
+    fn spawn_idle_reaper(self: &Arc<Self>, idle_timeout: Duration) {
+        let pool = Arc::downgrade(self);
+        tokio::spawn(async move {
+            let mut ticker =
+                tokio::time::interval((idle_timeout / 2).max(Duration::from_millis(10)));
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                ticker.tick().await;
+                match Weak::upgrade(&pool) {
+                    Some(pool) => pool.shrink_idle(idle_timeout).await,
+                    None => break,
+                }
+            }
+        });
+    }
+
+    async fn shrink_idle(&self, idle_timeout: Duration) {
+        let idle_timeout_ms = idle_timeout.as_millis() as u64;
+        loop {
+            let active = self.active_size();
+            if active <= self.min_size {
+                return;
+            }
+            let idx = active - 1;
+            let pooled = &self.connections[idx];
+            let now_ms = self.epoch.elapsed().as_millis() as u64;
+            if now_ms.saturating_sub(pooled.last_used_ms.load(Ordering::Relaxed)) < idle_timeout_ms
+            {
+                return;
+            }
+            let Ok(_permits) = pooled.permits.try_acquire_many(self.max_in_flight as u32) else {
+                return;
+            };
+            if self
+                .active
+                .compare_exchange(active, idx, Ordering::AcqRel, Ordering::Acquire)
+                .is_err()
+            {
+                return;
+            }
+            debug!("Shrinking pool to {} connections", idx);
+            pooled.slot.lock().await.connection = None;
+        }
+    }
+
+    /// Probes idle connections every `health_check.interval` until the pool is dropped.
+    pub(crate) fn spawn_health_check(self: &Arc<Self>, health_check: HealthCheck) {
+        let pool = Arc::downgrade(self);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(health_check.interval);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                match Weak::upgrade(&pool) {
+                    Some(pool) => pool.check_idle_connections(&health_check).await,
+                    None => break,
+                }
+            }
+        });
+    }
+
+    async fn check_idle_connections(&self, health_check: &HealthCheck) {
+        let idle_after_ms = health_check.idle_after.as_millis() as u64;
+        for (idx, pooled) in self.connections.iter().enumerate() {
+            let now_ms = self.epoch.elapsed().as_millis() as u64;
+            if now_ms.saturating_sub(pooled.last_used_ms.load(Ordering::Relaxed)) < idle_after_ms {
+                continue;
+            }
+            // Busy connections are evidently in use and report their own failures.
+            let Ok(_permits) = pooled.permits.try_acquire_many(self.max_in_flight as u32) else {
+                continue;
+            };
+            let (mut connection, generation) = {
+                let slot = pooled.slot.lock().await;
+                match &slot.connection {
+                    Some(connection) => (connection.clone(), slot.generation),
+                    None => continue,
+                }
+            };
+
+            let ping = redis::cmd("PING");
+            let probe = ping.query_async::<String>(&mut connection);
+            match tokio::time::timeout(health_check.ping_timeout, probe).await {
+                Ok(Ok(_)) => self.touch(idx),
+                Ok(Err(err)) if !is_connection_error(&err) => self.touch(idx),
+                Ok(Err(err)) => {
+                    warn!("Health check failed for pooled connection {}", idx);
+                    self.evict(idx, generation, &err).await;
+                }
+                Err(_) => {
+                    let err = RedisError::from(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        format!(
+                            "health check got no reply within {:?}",
+                            health_check.ping_timeout
+                        ),
+                    ));
+                    self.evict(idx, generation, &err).await;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::FutureExt;
+    use std::sync::atomic::AtomicBool;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+
+    /// A RESP server that answers `PING` with `PONG`, `FAIL` with an error and everything else
+    /// with `OK`. `DROP` closes the connection; `silent` stops all replies.
+    struct FakeServer {
+        client: redis::Client,
+        accepted: Arc<AtomicUsize>,
+        silent: Arc<AtomicBool>,
+    }
+
+    impl FakeServer {
+        async fn start() -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let port = listener.local_addr().unwrap().port();
+            let accepted = Arc::new(AtomicUsize::new(0));
+            let silent = Arc::new(AtomicBool::new(false));
+            let (counter, mute) = (accepted.clone(), silent.clone());
+            tokio::spawn(async move {
+                while let Ok((stream, _)) = listener.accept().await {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                    tokio::spawn(serve(stream, mute.clone()));
+                }
+            });
+            let client = redis::Client::open(format!("redis://127.0.0.1:{port}")).unwrap();
+            Self {
+                client,
+                accepted,
+                silent,
+            }
+        }
+
+        fn accepted(&self) -> usize {
+            self.accepted.load(Ordering::SeqCst)
+        }
+
+        async fn pool(&self, options: PoolOptions) -> Arc<ConnectionPool> {
+            ConnectionPool::new(&self.client, options).await.unwrap()
+        }
+    }
+
+    async fn serve(mut stream: TcpStream, silent: Arc<AtomicBool>) {
+        let mut buffer = Vec::new();
+        let mut read = [0; 4096];
+        loop {
+            while let Some((command, len)) = parse_command(&buffer) {
+                buffer.drain(..len);
+                let reply: &[u8] = match command.as_str() {
+                    "DROP" => return,
+                    "FAIL" => b"-ERR failed\r\n",
+                    "PING" => b"+PONG\r\n",
+                    _ => b"+OK\r\n",
+                };
+                if !silent.load(Ordering::SeqCst) && stream.write_all(reply).await.is_err() {
+                    return;
+                }
+            }
+            match stream.read(&mut read).await {
+                Ok(0) | Err(_) => return,
+                Ok(n) => buffer.extend_from_slice(&read[..n]),
+            }
+        }
+    }
+
+    /// Name of the first complete command in `buffer` and its length in bytes.
+    fn parse_command(buffer: &[u8]) -> Option<(String, usize)> {
+        fn line(buffer: &[u8], at: usize) -> Option<(&[u8], usize)> {
+            let end = buffer[at..].windows(2).position(|w| w == b"\r\n")? + at;
+            Some((&buffer[at..end], end + 2))
+        }
+        let number = |bytes: &[u8]| std::str::from_utf8(&bytes[1..]).ok()?.parse::<usize>().ok();
+
+        let (header, mut at) = line(buffer, 0)?;
+        let mut args = Vec::new();
+        for _ in 0..number(header)? {
+            let (header, start) = line(buffer, at)?;
+            let end = start + number(header)?;
+            if buffer.len() < end + 2 {
+                return None;
+            }
+            args.push(String::from_utf8_lossy(&buffer[start..end]).to_uppercase());
+            at = end + 2;
+        }
+        Some((args.into_iter().next().unwrap_or_default(), at))
+    }
+
+    async fn run(pool: &ConnectionPool, command: &'static str) -> RedisResult<String> {
+        pool.with_next_connection("test", |connection| {
+            async move { redis::cmd(command).query_async(connection).await }.boxed()
+        })
+        .await
+    }
+
+    fn is_connected(pool: &ConnectionPool, idx: usize) -> bool {
+        pool.connections[idx]
+            .slot
+            .try_lock()
+            .unwrap()
+            .connection
+            .is_some()
+    }
+
+    #[tokio::test]
+    async fn connection_errors_evict_and_the_next_call_reconnects() {
+        let server = FakeServer::start().await;
+        let pool = server.pool(PoolOptions::with_size(1)).await;
+        assert_eq!(run(&pool, "PING").await.unwrap(), "PONG");
+        assert_eq!(server.accepted(), 1);
+
+        let err = run(&pool, "DROP").await.unwrap_err();
+        assert!(is_connection_error(&err), "{err}");
+        assert!(!is_connected(&pool, 0));
+
+        assert_eq!(run(&pool, "PING").await.unwrap(), "PONG");
+        assert_eq!(server.accepted(), 2);
+        assert!(is_connected(&pool, 0));
+    }
+
+    #[tokio::test]
+    async fn command_errors_keep_the_connection() {
+        let server = FakeServer::start().await;
+        let pool = server.pool(PoolOptions::with_size(1)).await;
+        assert!(run(&pool, "FAIL").await.is_err());
+        assert!(is_connected(&pool, 0));
+        assert_eq!(server.accepted(), 1);
+    }
+
+    #[tokio::test]
+    async fn health_check_evicts_unresponsive_connections() {
+        let server = FakeServer::start().await;
+        let pool = server.pool(PoolOptions::with_size(2)).await;
+        let health_check = HealthCheck {
+            interval: Duration::from_secs(60),
+            idle_after: Duration::ZERO,
+            ping_timeout: Duration::from_millis(50),
+        };
+
+        pool.check_idle_connections(&health_check).await;
+        assert!(is_connected(&pool, 0) && is_connected(&pool, 1));
+
+        server.silent.store(true, Ordering::SeqCst);
+        pool.check_idle_connections(&health_check).await;
+        assert!(!is_connected(&pool, 0) && !is_connected(&pool, 1));
+
+        server.silent.store(false, Ordering::SeqCst);
+        assert_eq!(run(&pool, "PING").await.unwrap(), "PONG");
+        assert_eq!(server.accepted(), 3);
+    }
+
+    #[tokio::test]
+    async fn health_check_skips_busy_connections() {
+        let server = FakeServer::start().await;
+        let pool = server.pool(PoolOptions::with_size(1)).await;
+        let _busy = pool.connections[0].permits.try_acquire().unwrap();
+        server.silent.store(true, Ordering::SeqCst);
+        let health_check = HealthCheck {
+            interval: Duration::from_secs(60),
+            idle_after: Duration::ZERO,
+            ping_timeout: Duration::from_millis(50),
+        };
+        pool.check_idle_connections(&health_check).await;
+        assert!(is_connected(&pool, 0));
+    }
+
+    #[tokio::test]
+    async fn round_robin_rotates_through_connections() {
+        let server = FakeServer::start().await;
+        let pool = server.pool(PoolOptions::with_size(3)).await;
+        let mut picked = Vec::new();
+        for _ in 0..6 {
+            picked.push(pool.acquire().await.idx);
+        }
+        assert_eq!(picked, vec![0, 1, 2, 0, 1, 2]);
+    }
+
+    #[tokio::test]
+    async fn round_robin_skips_busy_connections() {
+        let server = FakeServer::start().await;
+        let pool = server.pool(PoolOptions::with_size(3)).await;
+        let _busy = pool.connections[1].permits.try_acquire().unwrap();
+        let picked: Vec<_> = [pool.acquire().await, pool.acquire().await]
+            .iter()
+            .map(|lease| lease.idx)
+            .collect();
+        assert_eq!(picked, vec![0, 2]);
+    }
+
+    #[tokio::test]
+    async fn least_in_flight_prefers_the_idlest_connection() {
+        let server = FakeServer::start().await;
+        let options = PoolOptions::with_size(3)
+            .with_selection(SelectionStrategy::LeastInFlight)
+            .with_max_in_flight_per_connection(4);
+        let pool = server.pool(options).await;
+        let _busy = [
+            pool.connections[0].permits.try_acquire_many(2).unwrap(),
+            pool.connections[2].permits.try_acquire().unwrap(),
+        ];
+        for _ in 0..3 {
+            assert_eq!(pool.acquire().await.idx, 1);
+        }
+    }
+
+    #[tokio::test]
+    async fn power_of_two_choices_prefers_the_idler_of_two() {
+        let server = FakeServer::start().await;
+        let options = PoolOptions::with_size(2)
+            .with_selection(SelectionStrategy::PowerOfTwoChoices)
+            .with_max_in_flight_per_connection(2);
+        let pool = server.pool(options).await;
+        let _busy = pool.connections[0].permits.try_acquire().unwrap();
+        for _ in 0..10 {
+            assert_eq!(pool.acquire().await.idx, 1);
+        }
+    }
+
+    #[tokio::test]
+    async fn fair_queue_serves_waiters_in_arrival_order() {
+        let server = FakeServer::start().await;
+        let options = PoolOptions::with_size(2).with_selection(SelectionStrategy::FairQueue);
+        let pool = server.pool(options).await;
+        let held = [pool.acquire().await, pool.acquire().await];
+
+        let served = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut waiters = Vec::new();
+        for id in 0..6 {
+            let (pool, served) = (pool.clone(), served.clone());
+            let (queued, is_queued) = tokio::sync::oneshot::channel();
+            waiters.push(tokio::spawn(async move {
+                let mut acquire = std::pin::pin!(pool.acquire());
+                assert!(futures::poll!(&mut acquire).is_pending());
+                queued.send(()).unwrap();
+                let _lease = acquire.await;
+                served.lock().unwrap().push(id);
+            }));
+            // The next waiter arrives only once this one is queued.
+            is_queued.await.unwrap();
+        }
+        drop(held);
+        for waiter in waiters {
+            waiter.await.unwrap();
+        }
+        assert_eq!(*served.lock().unwrap(), vec![0, 1, 2, 3, 4, 5]);
+    }
+
+    #[tokio::test]
+    async fn grows_when_every_connection_is_busy() {
+        let server = FakeServer::start().await;
+        let pool = server.pool(PoolOptions::with_bounds(1, 2)).await;
+        let first = pool.acquire().await;
+        let second = pool.acquire().await;
+        assert_eq!((first.idx, second.idx), (0, 1));
+        assert_eq!(pool.active_size(), 2);
+    }
+}
@@ -0,0 +1,284 @@
+use chrono::{DateTime, Utc};
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+use redis::{SetExpiry, ToRedisArgs};
+use std::time::Duration;
+
+/// How the `pipelined_*` write paths express a TTL to the server.
+///
+/// The relative modes send the TTL itself, the absolute ones `now + ttl` as a Unix timestamp.
+/// Second-precision modes round up to the next whole second, so a key never expires before its
+/// TTL has elapsed; use `Px` or `PxAt` for sub-second TTLs. Relative modes send at least one
+/// unit, as servers reject a zero TTL.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ExpiryMode {
+    /// `EX seconds` / `EXPIRE`.
+    Ex,
+    /// `PX milliseconds` / `PEXPIRE`.
+    Px,
+    /// `EXAT unix-seconds` / `EXPIREAT`.
+    #[default]
+    ExAt,
+    /// `PXAT unix-milliseconds` / `PEXPIREAT`.
+    PxAt,
+}
+
+impl ExpiryMode {
+    /// Argument of the mode's `SET` option or `EXPIRE`-family command for `ttl` from `now`.
+    pub fn value(self, now: DateTime<Utc>, ttl: Duration) -> u64 {
+        let ttl_ms = u64::try_from(ttl.as_millis()).unwrap_or(u64::MAX);
+        let at_ms = (now.timestamp_millis() as u64).saturating_add(ttl_ms);
+        match self {
+            ExpiryMode::Ex => ttl_ms.div_ceil(1000).max(1),
+            ExpiryMode::Px => ttl_ms.max(1),
+            ExpiryMode::ExAt => at_ms.div_ceil(1000),
+            ExpiryMode::PxAt => at_ms,
+        }
+    }
+
+    /// Name of the `SET` option, e.g. `PXAT`.
+    pub fn set_option(self) -> &'static str {
+        match self {
+            ExpiryMode::Ex => "EX",
+            ExpiryMode::Px => "PX",
+            ExpiryMode::ExAt => "EXAT",
+            ExpiryMode::PxAt => "PXAT",
+        }
+    }
+
+    /// Name of the standalone command, e.g. `PEXPIREAT`.
+    pub fn expire_command(self) -> &'static str {
+        match self {
+            ExpiryMode::Ex => "EXPIRE",
+            ExpiryMode::Px => "PEXPIRE",
+            ExpiryMode::ExAt => "EXPIREAT",
+            ExpiryMode::PxAt => "PEXPIREAT",
+        }
+    }
+
+    /// `SET` option carrying `value`, as computed by `ExpiryMode::value`.
+    pub fn set_expiry(self, value: u64) -> SetExpiry {
+        match self {
+            ExpiryMode::Ex => SetExpiry::EX(value),
+            ExpiryMode::Px => SetExpiry::PX(value),
+            ExpiryMode::ExAt => SetExpiry::EXAT(value),
+            ExpiryMode::PxAt => SetExpiry::PXAT(value),
+        }
+    }
+}
+
+/// Expiry of a single item, for the `*_with_item_expiry` write paths.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Expiry {
+    /// The key does not expire. Writing it clears any TTL it had.
+    Never,
+    /// The key expires this long after it is written.
+    After(Duration),
+    /// The key expires at this instant.
+    At(DateTime<Utc>),
+}
+
+impl Expiry {
+    /// TTL from `now`, `None` for `Expiry::Never`. Instants that have already passed give a 1 ms
+    /// TTL, the shortest the server accepts.
+    pub fn ttl(self, now: DateTime<Utc>) -> Option<Duration> {
+        match self {
+            Expiry::Never => None,
+            Expiry::After(ttl) => Some(ttl),
+            Expiry::At(at) => Some(
+                (at - now)
+                    .to_std()
+                    .unwrap_or_default()
+                    .max(Duration::from_millis(1)),
+            ),
+        }
+    }
+}
+
+/// Random extra TTL that spreads the expiry of keys written together, so that a large sync does not
+/// expire, and get refetched, all at once.
+///
+/// Each key gets `ttl + offset` with the offset in `[0, max]` at millisecond granularity. When the
+/// random variants are given a `seed`, each key's offset is drawn from an RNG seeded with a hash of
+/// the key and `seed`, which makes the offsets reproducible without repeating them across chunks.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TtlJitter {
+    /// Offset drawn uniformly from `[0, max]`.
+    Uniform { max: Duration, seed: Option<u64> },
+    /// Offset drawn uniformly from `[0, percent% of the TTL]`.
+    Percent { percent: f64, seed: Option<u64> },
+    /// Offset in `[0, max]` derived from a hash of the key and `seed`. A key gets the same offset
+    /// on every write, regardless of how the input is chunked.
+    KeyHash { max: Duration, seed: u64 },
+}
+
+impl TtlJitter {
+    /// Largest offset the jitter adds to `ttl`.
+    pub fn max_offset(self, ttl: Duration) -> Duration {
+        match self {
+            TtlJitter::Uniform { max, .. } | TtlJitter::KeyHash { max, .. } => max,
+            TtlJitter::Percent { percent, .. } => {
+                let secs = ttl.as_secs_f64() * percent.max(0.0) / 100.0;
+                Duration::try_from_secs_f64(secs).unwrap_or(Duration::MAX)
+            }
+        }
+    }
+}
+
+enum JitterSource {
+    None,
+    Random(SmallRng),
+    Seeded(u64),
+    KeyHash(u64),
+}
+
+/// Applies the client's `TtlJitter` to the TTLs of the keys of one chunk.
+pub(crate) struct TtlSampler {
+    jitter: Option<TtlJitter>,
+    source: JitterSource,
+}
+
+impl TtlSampler {
+    pub(crate) fn new(jitter: Option<TtlJitter>) -> Self {
+        let source = match jitter {
+            None => JitterSource::None,
+            Some(TtlJitter::Uniform { seed, .. } | TtlJitter::Percent { seed, .. }) => match seed {
+                Some(seed) => JitterSource::Seeded(seed),
+                None => JitterSource::Random(SmallRng::from_rng(&mut rand::rng())),
+            },
+            Some(TtlJitter::KeyHash { seed, .. }) => JitterSource::KeyHash(seed),
+        };
+        Self { jitter, source }
+    }
+
+    /// Jittered `ttl` of `key`.
+    pub(crate) fn ttl<K: ToRedisArgs>(&mut self, key: &K, ttl: Duration) -> Duration {
+        let max_offset = self
+            .jitter
+            .map_or(Duration::ZERO, |jitter| jitter.max_offset(ttl));
+        let max_offset_ms = u64::try_from(max_offset.as_millis()).unwrap_or(u64::MAX);
+        if max_offset_ms == 0 {
+            return ttl;
+        }
+        let offset_ms = match &mut self.source {
+            JitterSource::None => 0,
+            JitterSource::Random(rng) => rng.random_range(0..=max_offset_ms),
+            JitterSource::Seeded(seed) => {
+                SmallRng::seed_from_u64(key_hash(key, *seed)).random_range(0..=max_offset_ms)
+            }
+            JitterSource::KeyHash(seed) => match max_offset_ms.checked_add(1) {
+                Some(offsets) => key_hash(key, *seed) % offsets,
+                None => key_hash(key, *seed),
+            },
+        };
+        ttl.saturating_add(Duration::from_millis(offset_ms))
+    }
+}
+
+/// FNV-1a over the key's arguments, finished with the SplitMix64 mixer. Stable across processes
+/// and releases, unlike `std::hash`.
+fn key_hash<K: ToRedisArgs>(key: &K, seed: u64) -> u64 {
+    let mut hash = 0xcbf2_9ce4_8422_2325 ^ seed;
+    for arg in key.to_redis_args() {
+        for byte in arg {
+            hash ^= u64::from(byte);
+            hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+    hash ^= hash >> 30;
+    hash = hash.wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    hash ^= hash >> 27;
+    hash = hash.wrapping_mul(0x94d0_49bb_1331_11eb);
+    hash ^ (hash >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn now() -> DateTime<Utc> {
+        Utc.timestamp_millis_opt(1_700_000_000_250).unwrap()
+    }
+
+    #[test]
+    fn relative_modes_never_send_a_zero_ttl() {
+        assert_eq!(ExpiryMode::Ex.value(now(), Duration::ZERO), 1);
+        assert_eq!(ExpiryMode::Px.value(now(), Duration::ZERO), 1);
+        assert_eq!(ExpiryMode::Px.value(now(), Duration::from_micros(300)), 1);
+    }
+
+    #[test]
+    fn second_modes_round_up() {
+        assert_eq!(ExpiryMode::Ex.value(now(), Duration::from_millis(1_001)), 2);
+        assert_eq!(ExpiryMode::Ex.value(now(), Duration::from_secs(60)), 60);
+        assert_eq!(
+            ExpiryMode::ExAt.value(now(), Duration::from_secs(60)),
+            1_700_000_061
+        );
+        assert_eq!(
+            ExpiryMode::PxAt.value(now(), Duration::from_secs(60)),
+            1_700_000_060_250
+        );
+    }
+
+    fn offsets(jitter: TtlJitter, keys: std::ops::Range<u32>) -> Vec<Duration> {
+        let mut sampler = TtlSampler::new(Some(jitter));
+        keys.map(|key| sampler.ttl(&format!("key:{key}"), Duration::ZERO))
+            .collect()
+    }
+
+    #[test]
+    fn seeded_jitter_is_reproducible_and_differs_across_chunks() {
+        let jitter = TtlJitter::Uniform {
+            max: Duration::from_secs(3600),
+            seed: Some(7),
+        };
+        let first = offsets(jitter, 0..64);
+        assert_eq!(first, offsets(jitter, 0..64));
+        assert_ne!(first, offsets(jitter, 64..128));
+        assert!(
+            first
+                .iter()
+                .all(|offset| *offset <= Duration::from_secs(3600))
+        );
+    }
+
+    #[test]
+    fn seeded_jitter_does_not_depend_on_chunking() {
+        let jitter = TtlJitter::Percent {
+            percent: 50.0,
+            seed: Some(7),
+        };
+        let mut sampler = TtlSampler::new(Some(jitter));
+        let ttl = Duration::from_secs(600);
+        let whole: Vec<_> = (0..8).map(|key| sampler.ttl(&key, ttl)).collect();
+        let mut split: Vec<_> = Vec::new();
+        for chunk in [0..3, 3..8] {
+            let mut sampler = TtlSampler::new(Some(jitter));
+            split.extend(chunk.map(|key| sampler.ttl(&key, ttl)));
+        }
+        assert_eq!(whole, split);
+    }
+
+    #[test]
+    fn huge_jitter_saturates_instead_of_overflowing() {
+        let ttl = Duration::from_secs(600);
+        for percent in [f64::INFINITY, 1e300, f64::NAN] {
+            let jitter = TtlJitter::Percent {
+                percent,
+                seed: Some(7),
+            };
+            let max_offset = jitter.max_offset(ttl);
+            let jittered = TtlSampler::new(Some(jitter)).ttl(&"key", ttl);
+            assert!(jittered >= ttl && jittered - ttl <= max_offset);
+        }
+        let jitter = TtlJitter::KeyHash {
+            max: Duration::from_millis(u64::MAX),
+            seed: 7,
+        };
+        let jittered = TtlSampler::new(Some(jitter)).ttl(&"key", Duration::MAX);
+        assert_eq!(jittered, Duration::MAX);
+        assert_eq!(ExpiryMode::PxAt.value(now(), jittered), u64::MAX);
+    }
+}
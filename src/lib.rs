@@ -1 +1,19 @@
+pub mod adaptive;
+pub mod batching_writer;
+pub mod call_options;
+mod cancellation;
+pub mod chunking;
+pub mod client_config;
+pub mod cluster_client;
+pub mod connection_pool;
+pub mod expiry;
+pub mod metrics;
 pub mod redis_client;
+pub mod retry;
+mod script;
+pub mod sentinel_client;
+mod spans;
+pub mod timeouts;
+pub mod tls;
+pub mod transaction;
+pub mod write_outcome;
@@ -1,3 +1,980 @@
-fn main() {
-    println!("Hello, world!");
+use clap::parser::ValueSource;
+use clap::{ArgMatches, Args, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use dragonfly_playground_rs::adaptive::AdaptiveOptions;
+use dragonfly_playground_rs::call_options::CallOptions;
+use dragonfly_playground_rs::chunking::ChunkingStrategy;
+use dragonfly_playground_rs::client_config::ConfigureClient;
+use dragonfly_playground_rs::cluster_client::AsyncRedisClientCluster;
+use dragonfly_playground_rs::connection_pool::{HealthCheck, PoolOptions, SelectionStrategy};
+use dragonfly_playground_rs::expiry::{Expiry, ExpiryMode, TtlJitter};
+use dragonfly_playground_rs::metrics::Metrics;
+use dragonfly_playground_rs::redis_client::{
+    AsyncRedisClient, AsyncRedisClientV1, RedisClientFactory, get_connection_info,
+};
+use dragonfly_playground_rs::retry::RetryPolicy;
+use dragonfly_playground_rs::timeouts::Timeouts;
+use dragonfly_playground_rs::tls::TlsOptions;
+use futures::{StreamExt, future, stream};
+use rand::Rng;
+use redis::{ErrorKind, ProtocolVersion, RedisError, RedisResult};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
+use tracing::warn;
+use tracing_subscriber::EnvFilter;
+
+/// Load generator for Dragonfly / Redis built on top of `AsyncRedisClient`.
+#[derive(Parser, Debug)]
+#[command(version, about)]
+struct Cli {
+    #[command(flatten)]
+    connection: ConnectionArgs,
+
+    #[command(flatten)]
+    client: ClientArgs,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Args, Debug)]
+struct ConnectionArgs {
+    /// Server address in `host:port` form or as a `redis://`, `rediss://` or `unix://` URL. The
+    /// cluster client accepts a comma-separated list of seed nodes, the sentinel client a
+    /// comma-separated list of sentinels.
+    #[arg(long, env = "REDIS_BENCH_SERVER", default_value = "127.0.0.1:6379")]
+    server: String,
+
+    /// Database slot to select.
+    #[arg(long, env = "REDIS_BENCH_DB", default_value_t = 0)]
+    db: i64,
+
+    #[arg(long, env = "REDIS_BENCH_USERNAME")]
+    username: Option<String>,
+
+    #[arg(long, env = "REDIS_BENCH_PASSWORD")]
+    password: Option<String>,
+
+    #[arg(long, value_enum, default_value_t = Protocol::Resp3)]
+    protocol: Protocol,
+
+    /// Name of the monitored master to resolve through the sentinels (sentinel client only).
+    #[arg(
+        long,
+        env = "REDIS_BENCH_SENTINEL_SERVICE_NAME",
+        default_value = "mymaster"
+    )]
+    sentinel_service_name: String,
+
+    /// Connect over TLS. Implied by any of the other `--tls-*` options and by `rediss://` URLs.
+    /// Requires the `tls` feature.
+    #[arg(long, env = "REDIS_BENCH_TLS")]
+    tls: bool,
+
+    /// PEM CA certificate to verify the server with instead of the platform trust store.
+    #[arg(long, env = "REDIS_BENCH_TLS_CA_CERT")]
+    tls_ca_cert: Option<PathBuf>,
+
+    /// PEM client certificate for mutual TLS.
+    #[arg(long, env = "REDIS_BENCH_TLS_CLIENT_CERT", requires = "tls_client_key")]
+    tls_client_cert: Option<PathBuf>,
+
+    /// PEM private key of `--tls-client-cert`.
+    #[arg(long, env = "REDIS_BENCH_TLS_CLIENT_KEY", requires = "tls_client_cert")]
+    tls_client_key: Option<PathBuf>,
+
+    /// Do not verify the server certificate. For development only.
+    #[arg(long)]
+    tls_insecure: bool,
+}
+
+impl ConnectionArgs {
+    fn tls_options(&self) -> Option<TlsOptions> {
+        let enabled = self.tls
+            || self.tls_insecure
+            || self.tls_ca_cert.is_some()
+            || self.tls_client_cert.is_some();
+        enabled.then(|| TlsOptions {
+            ca_cert: self.tls_ca_cert.clone(),
+            client_cert: self.tls_client_cert.clone(),
+            client_key: self.tls_client_key.clone(),
+            insecure: self.tls_insecure,
+        })
+    }
+}
+
+#[derive(Args, Debug)]
+struct ClientArgs {
+    /// Client implementation to drive.
+    #[arg(long, value_enum, default_value_t = ClientKind::Pooled)]
+    client: ClientKind,
+
+    /// Maximum number of items sent in a single chunk.
+    #[arg(long, env = "REDIS_BENCH_BATCH_SIZE", default_value_t = 10_000)]
+    batch_size: usize,
+
+    /// Optional cap on the serialized size of a chunk in bytes, applied on top of `--batch-size`.
+    #[arg(long)]
+    max_chunk_bytes: Option<usize>,
+
+    /// Number of chunks written concurrently (pooled client only).
+    #[arg(long, default_value_t = 4)]
+    write_parallelism: usize,
+
+    /// Maximum number of multiplexed write connections (pooled client only).
+    #[arg(long, default_value_t = 8)]
+    write_connection_pool_size: usize,
+
+    /// Write connections kept open at all times (pooled client only). Defaults to
+    /// `--write-connection-pool-size`, i.e. a fixed-size pool.
+    #[arg(long)]
+    write_pool_min_size: Option<usize>,
+
+    /// Number of `MGET` chunks read concurrently (pooled client only). Defaults to
+    /// `--write-parallelism`.
+    #[arg(long)]
+    read_parallelism: Option<usize>,
+
+    /// Maximum number of multiplexed read connections (pooled client only). Defaults to
+    /// `--write-connection-pool-size`.
+    #[arg(long)]
+    read_connection_pool_size: Option<usize>,
+
+    /// Read connections kept open at all times (pooled client only). Defaults to
+    /// `--read-connection-pool-size`.
+    #[arg(long)]
+    read_pool_min_size: Option<usize>,
+
+    /// Close pooled connections above the minimum size after they have been idle for this long.
+    #[arg(long)]
+    pool_idle_timeout_ms: Option<u64>,
+
+    /// Attempts per write chunk, including the first one. `1` disables retries.
+    #[arg(long, default_value_t = 1)]
+    retry_max_attempts: u32,
+
+    #[arg(long, default_value_t = 50)]
+    retry_initial_backoff_ms: u64,
+
+    #[arg(long, default_value_t = 2_000)]
+    retry_max_backoff_ms: u64,
+
+    /// How pooled connections are picked for each call (pooled and sentinel clients only).
+    #[arg(long, value_enum, default_value_t = Selection::RoundRobin)]
+    selection_strategy: Selection,
+
+    /// How the `pipelined-*` write modes send TTLs: relative `EX`/`PX` or absolute `EXAT`/`PXAT`.
+    #[arg(long, value_enum, default_value_t = TtlMode::Exat)]
+    expiry_mode: TtlMode,
+
+    /// Add a random offset of up to this many milliseconds to each key's TTL.
+    #[arg(long)]
+    ttl_jitter_ms: Option<u64>,
+
+    /// Add a random offset of up to this percentage of the TTL to each key's TTL.
+    #[arg(long, conflicts_with = "ttl_jitter_ms", value_parser = parse_percent)]
+    ttl_jitter_percent: Option<f64>,
+
+    /// Derive the `--ttl-jitter-ms` offset from a hash of the key instead of drawing it at random.
+    #[arg(long, requires = "ttl_jitter_ms")]
+    ttl_jitter_by_key: bool,
+
+    /// Seed for the TTL jitter, making the offsets reproducible.
+    #[arg(long)]
+    ttl_jitter_seed: Option<u64>,
+
+    /// Wrap each chunk of the `pipelined-*` write modes in `MULTI`/`EXEC`.
+    #[arg(long)]
+    transactions: bool,
+
+    /// Calls that may share a pooled connection at the same time (pooled and sentinel clients
+    /// only).
+    #[arg(long, default_value_t = 1)]
+    max_in_flight_per_connection: usize,
+
+    /// PING pooled connections idle for this long and replace the ones that fail (pooled and
+    /// sentinel clients only).
+    #[arg(long)]
+    health_check_interval_ms: Option<u64>,
+
+    /// Give up connecting to a server after this many milliseconds.
+    #[arg(long)]
+    connect_timeout_ms: Option<u64>,
+
+    /// Fail a command or pipeline whose response takes longer than this many milliseconds.
+    #[arg(long)]
+    response_timeout_ms: Option<u64>,
+
+    /// Fail a call that waits longer than this many milliseconds for a pooled connection (pooled
+    /// and sentinel clients only).
+    #[arg(long)]
+    acquire_timeout_ms: Option<u64>,
+
+    /// Stop starting new chunks of an op after this many milliseconds.
+    #[arg(long)]
+    deadline_ms: Option<u64>,
+
+    /// Let the pooled client tune its write batch size and parallelism (AIMD), starting from
+    /// `--batch-size` and `--write-parallelism`.
+    #[arg(long)]
+    adaptive: bool,
+
+    /// Mean chunk latency above which `--adaptive` shrinks the batch size.
+    #[arg(long, default_value_t = 50)]
+    adaptive_target_latency_ms: u64,
+
+    /// Upper bound of the batch size chosen by `--adaptive`.
+    #[arg(long, default_value_t = 10_000)]
+    adaptive_max_batch_size: usize,
+
+    /// Upper bound of the write parallelism chosen by `--adaptive`. Capped at the write pool's
+    /// capacity.
+    #[arg(long, default_value_t = 16)]
+    adaptive_max_parallelism: usize,
+
+    /// Print the client metrics in the Prometheus text format after the run.
+    #[arg(long)]
+    print_metrics: bool,
+}
+
+impl ClientArgs {
+    fn chunking(&self) -> ChunkingStrategy {
+        ChunkingStrategy::new(self.batch_size, self.max_chunk_bytes)
+    }
+
+    fn read_parallelism(&self) -> usize {
+        self.read_parallelism.unwrap_or(self.write_parallelism)
+    }
+
+    fn write_pool(&self) -> PoolOptions {
+        self.pool_options(self.write_pool_min_size, self.write_connection_pool_size)
+    }
+
+    fn read_pool(&self) -> PoolOptions {
+        let max_size = self
+            .read_connection_pool_size
+            .unwrap_or(self.write_connection_pool_size);
+        self.pool_options(self.read_pool_min_size, max_size)
+    }
+
+    fn pool_options(&self, min_size: Option<usize>, max_size: usize) -> PoolOptions {
+        let options = PoolOptions::with_bounds(min_size.unwrap_or(max_size), max_size)
+            .with_selection(self.selection_strategy.into())
+            .with_max_in_flight_per_connection(self.max_in_flight_per_connection)
+            .with_timeouts(self.timeouts());
+        match self.pool_idle_timeout_ms {
+            Some(idle_timeout_ms) => {
+                options.with_idle_timeout(Duration::from_millis(idle_timeout_ms))
+            }
+            None => options,
+        }
+    }
+
+    fn timeouts(&self) -> Timeouts {
+        Timeouts {
+            connect: self.connect_timeout_ms.map(Duration::from_millis),
+            response: self.response_timeout_ms.map(Duration::from_millis),
+            acquire: self.acquire_timeout_ms.map(Duration::from_millis),
+        }
+    }
+
+    fn deadline(&self) -> Option<Duration> {
+        self.deadline_ms.map(Duration::from_millis)
+    }
+
+    fn adaptive(&self) -> Option<AdaptiveOptions> {
+        self.adaptive.then(|| AdaptiveOptions {
+            target_latency: Duration::from_millis(self.adaptive_target_latency_ms),
+            max_batch_size: self.adaptive_max_batch_size,
+            max_parallelism: self.adaptive_max_parallelism,
+            ..AdaptiveOptions::default()
+        })
+    }
+
+    fn ttl_jitter(&self) -> Option<TtlJitter> {
+        let seed = self.ttl_jitter_seed;
+        match (self.ttl_jitter_ms, self.ttl_jitter_percent) {
+            (Some(max_ms), _) if self.ttl_jitter_by_key => Some(TtlJitter::KeyHash {
+                max: Duration::from_millis(max_ms),
+                seed: seed.unwrap_or_default(),
+            }),
+            (Some(max_ms), _) => Some(TtlJitter::Uniform {
+                max: Duration::from_millis(max_ms),
+                seed,
+            }),
+            (None, Some(percent)) => Some(TtlJitter::Percent { percent, seed }),
+            (None, None) => None,
+        }
+    }
+
+    fn health_check(&self) -> Option<HealthCheck> {
+        self.health_check_interval_ms
+            .map(|interval_ms| HealthCheck {
+                interval: Duration::from_millis(interval_ms),
+                idle_after: Duration::from_millis(interval_ms),
+                ..HealthCheck::default()
+            })
+    }
+
+    fn retry_policy(&self) -> RetryPolicy {
+        RetryPolicy::exponential(self.retry_max_attempts).with_backoff(
+            Duration::from_millis(self.retry_initial_backoff_ms),
+            Duration::from_millis(self.retry_max_backoff_ms),
+        )
+    }
+}
+
+/// Options of the connection pools and the adaptive controller, which only the pooled and sentinel
+/// clients have.
+const POOLED_ARGS: &[&str] = &[
+    "write_connection_pool_size",
+    "write_pool_min_size",
+    "read_parallelism",
+    "read_connection_pool_size",
+    "read_pool_min_size",
+    "pool_idle_timeout_ms",
+    "selection_strategy",
+    "max_in_flight_per_connection",
+    "health_check_interval_ms",
+    "acquire_timeout_ms",
+    "adaptive",
+    "adaptive_target_latency_ms",
+    "adaptive_max_batch_size",
+    "adaptive_max_parallelism",
+];
+
+/// Fails if any of the options `ids` was set, on the command line or in the environment, as
+/// `client` would ignore it.
+fn reject_args(matches: &ArgMatches, client: &str, ids: &[&str]) -> RedisResult<()> {
+    let given = ids.iter().find(|id| {
+        !matches!(
+            matches.value_source(id),
+            None | Some(ValueSource::DefaultValue)
+        )
+    });
+    match given {
+        Some(id) => Err(RedisError::from((
+            ErrorKind::InvalidClientConfig,
+            "Option not supported by the selected client",
+            format!(
+                "`--{}` is not supported by the {client} client",
+                id.replace('_', "-")
+            ),
+        ))),
+        None => Ok(()),
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Write random items.
+    Write {
+        #[command(flatten)]
+        workload: WorkloadArgs,
+        #[command(flatten)]
+        write: WriteArgs,
+    },
+    /// Read random keys with `multi_get`.
+    Read {
+        #[command(flatten)]
+        workload: WorkloadArgs,
+    },
+    /// Interleave reads and writes.
+    Mixed {
+        #[command(flatten)]
+        workload: WorkloadArgs,
+        #[command(flatten)]
+        write: WriteArgs,
+        /// Fraction of operations that are reads, in `[0, 1]`.
+        #[arg(long, default_value_t = 0.5)]
+        read_ratio: f64,
+    },
+    /// Send `PING` commands.
+    Ping {
+        #[command(flatten)]
+        run: RunArgs,
+    },
+}
+
+#[derive(Args, Debug, Clone)]
+struct RunArgs {
+    /// How long to run the workload for. Defaults to 10 seconds unless `--ops` is set.
+    #[arg(long)]
+    duration_secs: Option<u64>,
+
+    /// Stop after this many operations.
+    #[arg(long)]
+    ops: Option<u64>,
+
+    /// Number of operations in flight at the same time.
+    #[arg(long, default_value_t = 1)]
+    concurrency: usize,
+}
+
+#[derive(Args, Debug, Clone)]
+struct WorkloadArgs {
+    #[command(flatten)]
+    run: RunArgs,
+
+    /// Number of items written or read by a single operation.
+    #[arg(long, default_value_t = 1_000)]
+    items_per_op: usize,
+
+    /// Keys are drawn uniformly from `[0, keyspace)`.
+    #[arg(long, default_value_t = 100_000)]
+    keyspace: u64,
+
+    #[arg(long, default_value = "dfly:")]
+    key_prefix: String,
+
+    /// Size of each generated value in bytes.
+    #[arg(long, default_value_t = 20)]
+    value_size: usize,
+}
+
+#[derive(Args, Debug, Clone)]
+struct WriteArgs {
+    #[arg(long, value_enum, default_value_t = WriteMode::MultiSet)]
+    mode: WriteMode,
+
+    /// TTL used by the `pipelined-*` modes.
+    #[arg(long, env = "REDIS_BENCH_TTL_SECS", default_value_t = 300)]
+    ttl_secs: u64,
+
+    /// TTL in milliseconds, overrides `--ttl-secs`. Sub-second TTLs need `--expiry-mode px` or
+    /// `pxat`.
+    #[arg(long)]
+    ttl_ms: Option<u64>,
+
+    /// Number of distinct expiries the `*-item-expiry` modes spread items over: item `i` gets
+    /// `(1 + i % n) * ttl`. `0` writes every item without expiry.
+    #[arg(long, default_value_t = 4)]
+    ttl_groups: u32,
+}
+
+impl WriteArgs {
+    fn ttl(&self) -> Duration {
+        match self.ttl_ms {
+            Some(ttl_ms) => Duration::from_millis(ttl_ms),
+            None => Duration::from_secs(self.ttl_secs),
+        }
+    }
+
+    fn item_expiry(&self, idx: usize) -> Expiry {
+        match self.ttl_groups {
+            0 => Expiry::Never,
+            groups => Expiry::After(self.ttl() * (1 + idx as u32 % groups)),
+        }
+    }
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+enum Protocol {
+    Resp2,
+    Resp3,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+enum ClientKind {
+    V1,
+    Pooled,
+    Cluster,
+    Sentinel,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+enum Selection {
+    RoundRobin,
+    LeastInFlight,
+    PowerOfTwoChoices,
+    FairQueue,
+}
+
+impl From<Selection> for SelectionStrategy {
+    fn from(selection: Selection) -> Self {
+        match selection {
+            Selection::RoundRobin => SelectionStrategy::RoundRobin,
+            Selection::LeastInFlight => SelectionStrategy::LeastInFlight,
+            Selection::PowerOfTwoChoices => SelectionStrategy::PowerOfTwoChoices,
+            Selection::FairQueue => SelectionStrategy::FairQueue,
+        }
+    }
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+enum TtlMode {
+    Ex,
+    Px,
+    Exat,
+    Pxat,
+}
+
+impl From<TtlMode> for ExpiryMode {
+    fn from(mode: TtlMode) -> Self {
+        match mode {
+            TtlMode::Ex => ExpiryMode::Ex,
+            TtlMode::Px => ExpiryMode::Px,
+            TtlMode::Exat => ExpiryMode::ExAt,
+            TtlMode::Pxat => ExpiryMode::PxAt,
+        }
+    }
+}
+
+/// Parses a finite, non-negative percentage.
+fn parse_percent(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(percent) if percent.is_finite() && percent >= 0.0 => Ok(percent),
+        Ok(_) => Err("must be a finite, non-negative number".to_string()),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+enum WriteMode {
+    MultiSet,
+    PipelinedMultiSetWithExpiry,
+    PipelinedSetWithExpiry,
+    PipelinedSetWithExpiryManual,
+    PipelinedSetWithExpiryScript,
+    PipelinedMultiSetWithItemExpiry,
+    PipelinedSetWithItemExpiry,
+    PipelinedSetWithItemExpiryManual,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OpKind {
+    Read,
+    Write,
+    Ping,
+}
+
+impl OpKind {
+    fn name(&self) -> &'static str {
+        match self {
+            OpKind::Read => "read",
+            OpKind::Write => "write",
+            OpKind::Ping => "ping",
+        }
+    }
+}
+
+enum Op {
+    Read(Vec<String>),
+    Write(Vec<(String, Vec<u8>)>),
+    Ping,
+}
+
+impl Op {
+    fn kind(&self) -> OpKind {
+        match self {
+            Op::Read(_) => OpKind::Read,
+            Op::Write(_) => OpKind::Write,
+            Op::Ping => OpKind::Ping,
+        }
+    }
+
+    fn items(&self) -> usize {
+        match self {
+            Op::Read(keys) => keys.len(),
+            Op::Write(items) => items.len(),
+            Op::Ping => 0,
+        }
+    }
+}
+
+struct OpStats {
+    kind: OpKind,
+    latencies: Vec<Duration>,
+    items: u64,
+    errors: u64,
+}
+
+impl OpStats {
+    fn new(kind: OpKind) -> Self {
+        Self {
+            kind,
+            latencies: Vec::new(),
+            items: 0,
+            errors: 0,
+        }
+    }
+
+    fn percentile(sorted: &[Duration], p: f64) -> Duration {
+        if sorted.is_empty() {
+            return Duration::ZERO;
+        }
+        let rank = ((p / 100.0) * sorted.len() as f64).ceil() as usize;
+        sorted[rank.clamp(1, sorted.len()) - 1]
+    }
+
+    fn report(&mut self, elapsed: Duration) {
+        if self.latencies.is_empty() {
+            return;
+        }
+        self.latencies.sort_unstable();
+        let ops = self.latencies.len() as u64;
+        let secs = elapsed.as_secs_f64();
+        println!(
+            "{}: {} ops ({} errors), {:.1} ops/s, {} items, {:.1} items/s",
+            self.kind.name(),
+            ops,
+            self.errors,
+            ops as f64 / secs,
+            self.items,
+            self.items as f64 / secs
+        );
+        let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+        println!(
+            "  latency ms: min {:.3}, p50 {:.3}, p90 {:.3}, p99 {:.3}, p99.9 {:.3}, max {:.3}",
+            ms(self.latencies[0]),
+            ms(Self::percentile(&self.latencies, 50.0)),
+            ms(Self::percentile(&self.latencies, 90.0)),
+            ms(Self::percentile(&self.latencies, 99.0)),
+            ms(Self::percentile(&self.latencies, 99.9)),
+            ms(self.latencies[self.latencies.len() - 1]),
+        );
+    }
+}
+
+struct Workload {
+    args: WorkloadArgs,
+    read_ratio: f64,
+}
+
+impl Workload {
+    fn random_key(&self, rng: &mut impl Rng) -> String {
+        format!(
+            "{}{}",
+            self.args.key_prefix,
+            rng.random_range(0..self.args.keyspace.max(1))
+        )
+    }
+
+    fn next_op(&self) -> Op {
+        let mut rng = rand::rng();
+        if rng.random_bool(self.read_ratio.clamp(0.0, 1.0)) {
+            let keys = (0..self.args.items_per_op)
+                .map(|_| self.random_key(&mut rng))
+                .collect();
+            Op::Read(keys)
+        } else {
+            let items = (0..self.args.items_per_op)
+                .map(|_| {
+                    let mut value = vec![0u8; self.args.value_size];
+                    rng.fill(&mut value[..]);
+                    (self.random_key(&mut rng), value)
+                })
+                .collect();
+            Op::Write(items)
+        }
+    }
+}
+
+fn with_item_expiry<'a, K, V>(
+    items: &'a [(K, V)],
+    write: &WriteArgs,
+) -> Vec<(&'a K, &'a V, Expiry)> {
+    items
+        .iter()
+        .enumerate()
+        .map(|(idx, (k, v))| (k, v, write.item_expiry(idx)))
+        .collect()
+}
+
+async fn execute_op<C: AsyncRedisClient>(
+    client: &C,
+    op: &Op,
+    write: Option<&WriteArgs>,
+    call: &CallOptions,
+) -> RedisResult<()> {
+    match op {
+        Op::Read(keys) => client.multi_get::<_, Vec<u8>>(keys, call).await.map(|_| ()),
+        Op::Write(items) => {
+            let write = write.expect("write op requires write args");
+            let ttl = write.ttl();
+            let outcome = match write.mode {
+                WriteMode::MultiSet => client.multi_set(items, call).await,
+                WriteMode::PipelinedMultiSetWithExpiry => {
+                    client
+                        .pipelined_multi_set_with_expiry(items, ttl, call)
+                        .await
+                }
+                WriteMode::PipelinedSetWithExpiry => {
+                    client.pipelined_set_with_expiry(items, ttl, call).await
+                }
+                WriteMode::PipelinedSetWithExpiryManual => {
+                    client
+                        .pipelined_set_with_expiry_manual(items, ttl, call)
+                        .await
+                }
+                WriteMode::PipelinedSetWithExpiryScript => {
+                    client
+                        .pipelined_set_with_expiry_script(items, ttl, call)
+                        .await
+                }
+                WriteMode::PipelinedMultiSetWithItemExpiry => {
+                    let items = with_item_expiry(items, write);
+                    client
+                        .pipelined_multi_set_with_item_expiry(&items, call)
+                        .await
+                }
+                WriteMode::PipelinedSetWithItemExpiry => {
+                    let items = with_item_expiry(items, write);
+                    client.pipelined_set_with_item_expiry(&items, call).await
+                }
+                WriteMode::PipelinedSetWithItemExpiryManual => {
+                    let items = with_item_expiry(items, write);
+                    client
+                        .pipelined_set_with_item_expiry_manual(&items, call)
+                        .await
+                }
+            };
+            outcome.into_result()
+        }
+        Op::Ping => client.ping().await.map(|_| ()),
+    }
+}
+
+/// Runs `command` against `client` until `shutdown` is cancelled. Each op is a call that may take
+/// up to `deadline` and is cancelled by `shutdown`.
+async fn run<C: AsyncRedisClient>(
+    client: &C,
+    command: &Command,
+    deadline: Option<Duration>,
+    shutdown: &CancellationToken,
+) {
+    let (run, workload, write) = match command {
+        Command::Write { workload, write } => (
+            &workload.run,
+            Some(Workload {
+                args: workload.clone(),
+                read_ratio: 0.0,
+            }),
+            Some(write),
+        ),
+        Command::Read { workload } => (
+            &workload.run,
+            Some(Workload {
+                args: workload.clone(),
+                read_ratio: 1.0,
+            }),
+            None,
+        ),
+        Command::Mixed {
+            workload,
+            write,
+            read_ratio,
+        } => (
+            &workload.run,
+            Some(Workload {
+                args: workload.clone(),
+                read_ratio: *read_ratio,
+            }),
+            Some(write),
+        ),
+        Command::Ping { run } => (run, None, None),
+    };
+
+    let duration = match (run.duration_secs, run.ops) {
+        (Some(secs), _) => Duration::from_secs(secs),
+        (None, Some(_)) => Duration::MAX,
+        (None, None) => Duration::from_secs(10),
+    };
+    let op_limit = run.ops.unwrap_or(u64::MAX);
+    let started = Instant::now();
+
+    let mut results = stream::iter(0..op_limit)
+        .take_while(|_| future::ready(started.elapsed() < duration && !shutdown.is_cancelled()))
+        .map(|_| {
+            let op = workload.as_ref().map_or(Op::Ping, Workload::next_op);
+            async move {
+                let op_started = Instant::now();
+                let call = CallOptions::default().with_cancellation(shutdown.clone());
+                let call = match deadline {
+                    Some(deadline) => call.with_timeout(deadline),
+                    None => call,
+                };
+                let result = execute_op(client, &op, write, &call).await;
+                (op.kind(), op.items(), op_started.elapsed(), result)
+            }
+        })
+        .buffer_unordered(run.concurrency.max(1));
+
+    let mut stats = [
+        OpStats::new(OpKind::Write),
+        OpStats::new(OpKind::Read),
+        OpStats::new(OpKind::Ping),
+    ];
+    while let Some((kind, items, latency, result)) = results.next().await {
+        let entry = stats
+            .iter_mut()
+            .find(|s| s.kind == kind)
+            .expect("stats for every op kind");
+        entry.latencies.push(latency);
+        match result {
+            Ok(()) => entry.items += items as u64,
+            Err(err) => {
+                entry.errors += 1;
+                warn!("{} op failed: {}", kind.name(), err);
+            }
+        }
+    }
+    let elapsed = started.elapsed();
+
+    println!(
+        "Ran for {:.3} s against {}",
+        elapsed.as_secs_f64(),
+        client.server_adder()
+    );
+    for entry in stats.iter_mut() {
+        entry.report(elapsed);
+    }
+}
+
+#[tokio::main]
+async fn main() -> RedisResult<()> {
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::from_default_env())
+        .init();
+
+    let matches = Cli::command().get_matches();
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+
+    let protocol = match cli.connection.protocol {
+        Protocol::Resp2 => ProtocolVersion::RESP2,
+        Protocol::Resp3 => ProtocolVersion::RESP3,
+    };
+    let conn_infos: Vec<_> = cli
+        .connection
+        .server
+        .split(',')
+        .map(|server| {
+            get_connection_info(
+                server.trim().to_string(),
+                cli.connection.db,
+                protocol,
+                cli.connection.username.clone(),
+                cli.connection.password.clone(),
+            )
+        })
+        .collect::<RedisResult<_>>()?;
+    let conn_info = conn_infos[0].clone();
+    let tls = cli.connection.tls_options();
+    let data_conn_info = match &tls {
+        Some(tls) => tls.apply(conn_info.clone())?,
+        None => conn_info.clone(),
+    };
+    let metrics = cli.client.print_metrics.then(Metrics::new);
+    // Ctrl-C ends the run early: no new ops start and the ops in flight, which are cancelled by
+    // this token, stop starting new chunks, but chunks already in flight finish before the pools
+    // are closed.
+    let shutdown = CancellationToken::new();
+    tokio::spawn({
+        let shutdown = shutdown.clone();
+        async move {
+            if tokio::signal::ctrl_c().await.is_ok() {
+                warn!("Interrupted, waiting for in-flight chunks");
+                shutdown.cancel();
+            }
+        }
+    });
+    // Pooled and sentinel clients; the factory applies `tls` itself.
+    let factory = RedisClientFactory {
+        conn_info,
+        batch_size: cli.client.batch_size,
+        write_parallelism: cli.client.write_parallelism,
+        write_pool: cli.client.write_pool(),
+        read_parallelism: cli.client.read_parallelism(),
+        read_pool: cli.client.read_pool(),
+        max_chunk_bytes: cli.client.max_chunk_bytes,
+        retry_policy: cli.client.retry_policy(),
+        expiry_mode: cli.client.expiry_mode.into(),
+        ttl_jitter: cli.client.ttl_jitter(),
+        transactions: cli.client.transactions,
+        adaptive: cli.client.adaptive(),
+        health_check: cli.client.health_check(),
+        tls: tls.clone(),
+        metrics: metrics.clone(),
+    };
+
+    match cli.client.client {
+        ClientKind::V1 => {
+            reject_args(&matches, "v1", &["write_parallelism"])?;
+            reject_args(&matches, "v1", POOLED_ARGS)?;
+            let client = AsyncRedisClientV1::new_with_timeouts(
+                data_conn_info,
+                cli.client.batch_size,
+                cli.client.timeouts(),
+            )
+            .await?
+            .with_chunking(cli.client.chunking())
+            .with_retry_policy(cli.client.retry_policy())
+            .with_expiry_mode(cli.client.expiry_mode.into())
+            .with_transactions(cli.client.transactions)
+            .with_some(cli.client.ttl_jitter(), ConfigureClient::with_ttl_jitter)
+            .with_some(metrics.clone(), ConfigureClient::with_metrics);
+            println!("Using AsyncRedisClientV1 with {:?}", cli.client);
+            run(&client, &cli.command, cli.client.deadline(), &shutdown).await;
+        }
+        ClientKind::Pooled => {
+            let client = factory.create().await?;
+            println!("Using AsyncRedisClientPooled with {:?}", cli.client);
+            run(&client, &cli.command, cli.client.deadline(), &shutdown).await;
+            if let Some(settings) = client.adaptive_settings() {
+                println!(
+                    "Adaptive controller settled on batch size {} and write parallelism {}",
+                    settings.batch_size, settings.parallelism
+                );
+            }
+            client.shutdown().await;
+        }
+        ClientKind::Cluster => {
+            if tls.is_some() {
+                return Err(RedisError::from((
+                    ErrorKind::InvalidClientConfig,
+                    "The `--tls*` options are not supported by the cluster client",
+                )));
+            }
+            reject_args(&matches, "cluster", POOLED_ARGS)?;
+            let client = AsyncRedisClientCluster::new_with_timeouts(
+                conn_infos,
+                cli.client.batch_size,
+                cli.client.write_parallelism,
+                cli.client.timeouts(),
+            )
+            .await?
+            .with_chunking(cli.client.chunking())
+            .with_retry_policy(cli.client.retry_policy())
+            .with_expiry_mode(cli.client.expiry_mode.into())
+            .with_transactions(cli.client.transactions)
+            .with_some(cli.client.ttl_jitter(), ConfigureClient::with_ttl_jitter)
+            .with_some(metrics.clone(), ConfigureClient::with_metrics);
+            println!("Using AsyncRedisClientCluster with {:?}", cli.client);
+            run(&client, &cli.command, cli.client.deadline(), &shutdown).await;
+        }
+        ClientKind::Sentinel => {
+            // Sentinels take neither a database nor the data node credentials.
+            let sentinels = cli
+                .connection
+                .server
+                .split(',')
+                .map(|server| {
+                    get_connection_info(server.trim().to_string(), 0, protocol, None, None)
+                })
+                .collect::<RedisResult<_>>()?;
+            let client = factory
+                .create_sentinel(sentinels, cli.connection.sentinel_service_name.clone())
+                .await?;
+            println!(
+                "Using AsyncRedisClientSentinel for {} with {:?}",
+                client.service_name, cli.client
+            );
+            run(&client, &cli.command, cli.client.deadline(), &shutdown).await;
+            client.shutdown().await;
+        }
+    }
+    if let Some(metrics) = metrics {
+        print!("{}", metrics.render_prometheus());
+    }
+    Ok(())
 }
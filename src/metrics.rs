@@ -0,0 +1,404 @@
+use crate::adaptive::AdaptiveSettings;
+use crate::chunking::ChunkItem;
+use crate::write_outcome::WriteOutcome;
+use redis::RedisResult;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Upper bounds, in seconds, of the latency histogram buckets.
+const LATENCY_BUCKETS: [f64; 14] = [
+    0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+const OPERATION_DURATION: &str = "redis_client_operation_duration_seconds";
+const COMMAND_DURATION: &str = "redis_client_command_duration_seconds";
+const ITEMS_WRITTEN: &str = "redis_client_items_written_total";
+const BYTES_WRITTEN: &str = "redis_client_bytes_written_total";
+const FAILURES: &str = "redis_client_failures_total";
+const POOL_WAIT: &str = "redis_client_pool_wait_seconds";
+const IN_FLIGHT: &str = "redis_client_in_flight_commands";
+const ADAPTIVE_BATCH_SIZE: &str = "redis_client_adaptive_batch_size";
+const ADAPTIVE_PARALLELISM: &str = "redis_client_adaptive_write_parallelism";
+
+fn help(name: &str) -> &'static str {
+    match name {
+        OPERATION_DURATION => "Duration of AsyncRedisClient calls.",
+        COMMAND_DURATION => "Duration of single chunks (commands or pipelines), including retries.",
+        ITEMS_WRITTEN => "Items acknowledged by the server.",
+        BYTES_WRITTEN => "RESP-encoded key and value bytes acknowledged by the server.",
+        FAILURES => "Failed chunks and reads by error kind.",
+        POOL_WAIT => "Time spent waiting for a pooled connection.",
+        IN_FLIGHT => "Chunks currently being executed.",
+        ADAPTIVE_BATCH_SIZE => "Write chunk size currently chosen by the adaptive controller.",
+        ADAPTIVE_PARALLELISM => "Write parallelism currently chosen by the adaptive controller.",
+        _ => "",
+    }
+}
+
+type Labels = Vec<(&'static str, String)>;
+
+enum Series {
+    Counter(u64),
+    Gauge(i64),
+    Histogram {
+        buckets: [u64; LATENCY_BUCKETS.len()],
+        count: u64,
+        sum: f64,
+    },
+}
+
+impl Series {
+    fn histogram() -> Self {
+        Series::Histogram {
+            buckets: [0; LATENCY_BUCKETS.len()],
+            count: 0,
+            sum: 0.0,
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            Series::Counter(_) => "counter",
+            Series::Gauge(_) => "gauge",
+            Series::Histogram { .. } => "histogram",
+        }
+    }
+}
+
+/// Registry of client metrics, shared by every client it is attached to.
+///
+/// Series are labelled with the `server` (`server_adder()`) and `method` they were recorded for.
+/// `render_prometheus` produces the Prometheus text exposition format, ready to be served from the
+/// host service's `/metrics` endpoint.
+#[derive(Clone, Default)]
+pub struct Metrics {
+    series: Arc<Mutex<BTreeMap<(&'static str, Labels), Series>>>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn update(
+        &self,
+        name: &'static str,
+        labels: Labels,
+        init: fn() -> Series,
+        f: impl FnOnce(&mut Series),
+    ) {
+        let mut series = self.series.lock().expect("metrics lock poisoned");
+        f(series.entry((name, labels)).or_insert_with(init));
+    }
+
+    fn add(&self, name: &'static str, labels: Labels, value: u64) {
+        self.update(
+            name,
+            labels,
+            || Series::Counter(0),
+            |series| {
+                if let Series::Counter(total) = series {
+                    *total += value;
+                }
+            },
+        );
+    }
+
+    fn shift(&self, name: &'static str, labels: Labels, delta: i64) {
+        self.update(
+            name,
+            labels,
+            || Series::Gauge(0),
+            |series| {
+                if let Series::Gauge(value) = series {
+                    *value += delta;
+                }
+            },
+        );
+    }
+
+    fn set(&self, name: &'static str, labels: Labels, value: i64) {
+        self.update(
+            name,
+            labels,
+            || Series::Gauge(0),
+            |series| {
+                if let Series::Gauge(current) = series {
+                    *current = value;
+                }
+            },
+        );
+    }
+
+    fn observe(&self, name: &'static str, labels: Labels, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        self.update(name, labels, Series::histogram, |series| {
+            if let Series::Histogram {
+                buckets,
+                count,
+                sum,
+            } = series
+            {
+                if let Some(idx) = LATENCY_BUCKETS.iter().position(|&le| seconds <= le) {
+                    buckets[idx] += 1;
+                }
+                *count += 1;
+                *sum += seconds;
+            }
+        });
+    }
+
+    /// Binds the metrics to the client talking to `server`.
+    pub(crate) fn for_server(&self, server: String) -> ClientMetrics {
+        ClientMetrics {
+            metrics: self.clone(),
+            server,
+        }
+    }
+
+    /// Renders every series in the Prometheus text exposition format.
+    pub fn render_prometheus(&self) -> String {
+        let series = self.series.lock().expect("metrics lock poisoned");
+        let mut out = String::new();
+        let mut current = None;
+        for ((name, labels), value) in series.iter() {
+            if current != Some(*name) {
+                let _ = writeln!(out, "# HELP {} {}", name, help(name));
+                let _ = writeln!(out, "# TYPE {} {}", name, value.kind());
+                current = Some(*name);
+            }
+            match value {
+                Series::Counter(total) => {
+                    let _ = writeln!(out, "{}{} {}", name, render_labels(labels, None), total);
+                }
+                Series::Gauge(value) => {
+                    let _ = writeln!(out, "{}{} {}", name, render_labels(labels, None), value);
+                }
+                Series::Histogram {
+                    buckets,
+                    count,
+                    sum,
+                } => {
+                    let mut cumulative = 0;
+                    for (le, bucket) in LATENCY_BUCKETS.iter().zip(buckets) {
+                        cumulative += bucket;
+                        let le = le.to_string();
+                        let _ = writeln!(
+                            out,
+                            "{}_bucket{} {}",
+                            name,
+                            render_labels(labels, Some(&le)),
+                            cumulative
+                        );
+                    }
+                    let _ = writeln!(
+                        out,
+                        "{}_bucket{} {}",
+                        name,
+                        render_labels(labels, Some("+Inf")),
+                        count
+                    );
+                    let _ = writeln!(out, "{}_sum{} {}", name, render_labels(labels, None), sum);
+                    let _ = writeln!(
+                        out,
+                        "{}_count{} {}",
+                        name,
+                        render_labels(labels, None),
+                        count
+                    );
+                }
+            }
+        }
+        out
+    }
+}
+
+fn render_labels(labels: &Labels, le: Option<&str>) -> String {
+    let mut pairs: Vec<String> = labels
+        .iter()
+        .map(|(name, value)| format!("{}=\"{}\"", name, escape_label_value(value)))
+        .collect();
+    if let Some(le) = le {
+        pairs.push(format!("le=\"{}\"", le));
+    }
+    if pairs.is_empty() {
+        String::new()
+    } else {
+        format!("{{{}}}", pairs.join(","))
+    }
+}
+
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// `Metrics` bound to the `server` label of one client.
+#[derive(Clone)]
+pub(crate) struct ClientMetrics {
+    metrics: Metrics,
+    server: String,
+}
+
+impl ClientMetrics {
+    fn labels(&self, method: &'static str) -> Labels {
+        vec![
+            ("server", self.server.clone()),
+            ("method", method.to_string()),
+        ]
+    }
+
+    /// Records one chunk of `method` sent as `command`.
+    fn observe_command(&self, method: &'static str, command: &'static str, duration: Duration) {
+        let mut labels = self.labels(method);
+        labels.push(("command", command.to_string()));
+        self.metrics.observe(COMMAND_DURATION, labels, duration);
+    }
+
+    /// Records how long a call of `method` waited for a connection of `pool`.
+    pub(crate) fn observe_pool_wait(
+        &self,
+        pool: &'static str,
+        method: &'static str,
+        duration: Duration,
+    ) {
+        let mut labels = self.labels(method);
+        labels.push(("pool", pool.to_string()));
+        self.metrics.observe(POOL_WAIT, labels, duration);
+    }
+
+    /// Counts a chunk of `method` as in flight until the guard is dropped.
+    fn in_flight(&self, method: &'static str) -> InFlightGuard {
+        let labels = self.labels(method);
+        self.metrics.shift(IN_FLIGHT, labels.clone(), 1);
+        InFlightGuard {
+            metrics: self.metrics.clone(),
+            labels,
+        }
+    }
+
+    /// Records the write chunk size and parallelism chosen by the client's adaptive controller.
+    pub(crate) fn record_adaptive(&self, settings: AdaptiveSettings) {
+        let labels = vec![("server", self.server.clone())];
+        self.metrics.set(
+            ADAPTIVE_BATCH_SIZE,
+            labels.clone(),
+            settings.batch_size as i64,
+        );
+        self.metrics
+            .set(ADAPTIVE_PARALLELISM, labels, settings.parallelism as i64);
+    }
+
+    fn record_failure(&self, method: &'static str, kind: redis::ErrorKind, count: u64) {
+        let mut labels = self.labels(method);
+        labels.push(("error_kind", format!("{:?}", kind)));
+        self.metrics.add(FAILURES, labels, count);
+    }
+}
+
+struct InFlightGuard {
+    metrics: Metrics,
+    labels: Labels,
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.metrics
+            .shift(IN_FLIGHT, std::mem::take(&mut self.labels), -1);
+    }
+}
+
+/// Times one chunk of `method` sent as `command` and counts it as in flight meanwhile.
+pub(crate) async fn instrument_chunk<T>(
+    metrics: Option<&ClientMetrics>,
+    method: &'static str,
+    command: &'static str,
+    chunk: impl Future<Output = T>,
+) -> T {
+    let Some(metrics) = metrics else {
+        return chunk.await;
+    };
+    let _in_flight = metrics.in_flight(method);
+    let started = Instant::now();
+    let result = chunk.await;
+    metrics.observe_command(method, command, started.elapsed());
+    result
+}
+
+/// Records duration, written items and bytes and failed chunks of a write call.
+pub(crate) async fn instrument_write<T: ChunkItem>(
+    metrics: Option<&ClientMetrics>,
+    method: &'static str,
+    items: &[T],
+    write: impl Future<Output = WriteOutcome>,
+) -> WriteOutcome {
+    if metrics.is_none() {
+        return write.await;
+    }
+    instrument_counted_write(metrics, method, async {
+        let outcome = write.await;
+        let bytes = outcome
+            .succeeded
+            .iter()
+            .flat_map(|range| &items[range.clone()])
+            .map(ChunkItem::resp_len)
+            .sum();
+        (outcome, bytes)
+    })
+    .await
+}
+
+/// `instrument_write` for writes that do not hold all of their items and count the bytes of the
+/// chunks they wrote themselves.
+pub(crate) async fn instrument_counted_write(
+    metrics: Option<&ClientMetrics>,
+    method: &'static str,
+    write: impl Future<Output = (WriteOutcome, usize)>,
+) -> WriteOutcome {
+    let Some(metrics) = metrics else {
+        return write.await.0;
+    };
+    let started = Instant::now();
+    let (outcome, bytes) = write.await;
+    let labels = metrics.labels(method);
+    metrics
+        .metrics
+        .observe(OPERATION_DURATION, labels.clone(), started.elapsed());
+
+    metrics.metrics.add(
+        ITEMS_WRITTEN,
+        labels.clone(),
+        outcome.items_written() as u64,
+    );
+    metrics.metrics.add(BYTES_WRITTEN, labels, bytes as u64);
+    for failure in &outcome.failed {
+        metrics.record_failure(method, failure.error.kind(), 1);
+    }
+    outcome
+}
+
+/// Records duration and failure of a read call.
+pub(crate) async fn instrument_read<T>(
+    metrics: Option<&ClientMetrics>,
+    method: &'static str,
+    read: impl Future<Output = RedisResult<T>>,
+) -> RedisResult<T> {
+    let Some(metrics) = metrics else {
+        return read.await;
+    };
+    let started = Instant::now();
+    let result = read.await;
+    metrics.metrics.observe(
+        OPERATION_DURATION,
+        metrics.labels(method),
+        started.elapsed(),
+    );
+    if let Err(err) = &result {
+        metrics.record_failure(method, err.kind(), 1);
+    }
+    result
+}
@@ -1,40 +1,93 @@
+use crate::adaptive::{AdaptiveController, AdaptiveOptions, AdaptiveSettings};
+use crate::call_options::CallOptions;
+use crate::cancellation::ChunkGate;
+use crate::chunking::{
+    ChunkItem, ChunkingStrategy, FrameCost, MAX_ARRAY_HEADER_LEN, command_len, decimal_len,
+    resp_bulk_len,
+};
+use crate::client_config::{ClientConfig, ConfigureClient};
+use crate::connection_pool::{ConnectionPool, HealthCheck, PoolOptions};
+use crate::expiry::{Expiry, ExpiryMode, TtlJitter, TtlSampler};
+use crate::metrics::{Metrics, instrument_chunk, instrument_counted_write};
+use crate::retry::RetryPolicy;
+use crate::script::{SET_WITH_EXPIRY, SET_WITH_EXPIRY_LUA, exec_with_eval_fallback};
+use crate::spans::{call_span, record_chunks, trace_chunk, trace_write};
+use crate::timeouts::Timeouts;
+use crate::tls::TlsOptions;
+use crate::transaction::{CompareAndSet, compare_and_set};
+use crate::write_outcome::WriteOutcome;
 use chrono::{DateTime, Utc};
-use futures::future::BoxFuture;
-use futures::{StreamExt, stream};
-use redis::aio::{ConnectionManager, MultiplexedConnection};
+use futures::{Stream, StreamExt, TryStreamExt, future, stream};
+use redis::aio::ConnectionManager;
 use redis::{
-    AsyncCommands, ConnectionAddr, ConnectionInfo, Pipeline, ProtocolVersion, RedisConnectionInfo,
-    RedisResult, SetExpiry, SetOptions, ToRedisArgs,
+    AsyncCommands, ConnectionAddr, ConnectionInfo, ErrorKind, FromRedisValue, IntoConnectionInfo,
+    Pipeline, ProtocolVersion, RedisConnectionInfo, RedisError, RedisResult, SetOptions,
+    ToRedisArgs,
 };
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::collections::HashMap;
+use std::ops::Range;
+use std::pin::pin;
+use std::sync::Arc;
 use std::time::{Duration, Instant};
-use tokio::sync::Mutex;
-use tracing::{debug, info, warn};
+use tracing::{Span, debug, info, warn};
 
+/// Client of a Redis deployment. The data methods take the `CallOptions` that limit the call.
 pub trait AsyncRedisClient {
     fn ping(&self) -> impl Future<Output = RedisResult<String>>;
 
-    fn multi_get(
+    /// Reads `keys` with `MGET`, chunked like the write paths. Values are returned in the order of
+    /// `keys`, with `None` for missing keys.
+    fn multi_get<K: ToRedisArgs + Sync + Send, V: FromRedisValue + Send>(
         &self,
-        keys: Vec<String>,
-    ) -> impl Future<Output = RedisResult<Vec<Option<String>>>>;
+        keys: &[K],
+        call: &CallOptions,
+    ) -> impl Future<Output = RedisResult<Vec<Option<V>>>>;
 
     fn multi_set<K: ToRedisArgs + Sync + Send, V: ToRedisArgs + Sync + Send>(
         &self,
         items: &[(K, V)],
-    ) -> impl Future<Output = RedisResult<()>>;
+        call: &CallOptions,
+    ) -> impl Future<Output = WriteOutcome>;
 
-    fn pipelined_multi_set_with_expiry<K: ToRedisArgs + Sync + Send, V: ToRedisArgs + Sync + Send>(
+    /// Writes `items` with `ttl` through the pipelines of `kind`. The `pipelined_*_with_expiry*`
+    /// methods are shorthands for it.
+    fn pipelined_write<K: ToRedisArgs + Sync + Send, V: ToRedisArgs + Sync + Send>(
         &self,
+        kind: PipelinedWrite,
         items: &[(K, V)],
         ttl: Duration,
-    ) -> impl Future<Output = RedisResult<()>>;
+        call: &CallOptions,
+    ) -> impl Future<Output = WriteOutcome>;
+
+    /// Writes `items`, each with its own expiry, through the pipelines of `kind`. The
+    /// `pipelined_*_with_item_expiry*` methods are shorthands for it.
+    fn pipelined_item_write<K: ToRedisArgs + Sync + Send, V: ToRedisArgs + Sync + Send>(
+        &self,
+        kind: PipelinedItemWrite,
+        items: &[(K, V, Expiry)],
+        call: &CallOptions,
+    ) -> impl Future<Output = WriteOutcome>;
+
+    fn pipelined_multi_set_with_expiry<
+        K: ToRedisArgs + Sync + Send,
+        V: ToRedisArgs + Sync + Send,
+    >(
+        &self,
+        items: &[(K, V)],
+        ttl: Duration,
+        call: &CallOptions,
+    ) -> impl Future<Output = WriteOutcome> {
+        self.pipelined_write(PipelinedWrite::MultiSetWithExpiry, items, ttl, call)
+    }
 
     fn pipelined_set_with_expiry<K: ToRedisArgs + Sync + Send, V: ToRedisArgs + Sync + Send>(
         &self,
         items: &[(K, V)],
         ttl: Duration,
-    ) -> impl Future<Output = RedisResult<()>>;
+        call: &CallOptions,
+    ) -> impl Future<Output = WriteOutcome> {
+        self.pipelined_write(PipelinedWrite::SetWithExpiry, items, ttl, call)
+    }
 
     fn pipelined_set_with_expiry_manual<
         K: ToRedisArgs + Sync + Send,
@@ -43,7 +96,61 @@ pub trait AsyncRedisClient {
         &self,
         items: &[(K, V)],
         ttl: Duration,
-    ) -> impl Future<Output = RedisResult<()>>;
+        call: &CallOptions,
+    ) -> impl Future<Output = WriteOutcome> {
+        self.pipelined_write(PipelinedWrite::SetWithExpiryManual, items, ttl, call)
+    }
+
+    /// Sets each chunk with its expiry atomically through a Lua script, invoked with `EVALSHA` and
+    /// resent with `EVAL` when the server does not have it yet.
+    fn pipelined_set_with_expiry_script<
+        K: ToRedisArgs + Sync + Send,
+        V: ToRedisArgs + Sync + Send,
+    >(
+        &self,
+        items: &[(K, V)],
+        ttl: Duration,
+        call: &CallOptions,
+    ) -> impl Future<Output = WriteOutcome> {
+        self.pipelined_write(PipelinedWrite::SetWithExpiryScript, items, ttl, call)
+    }
+
+    /// Like `pipelined_multi_set_with_expiry`, with each item's own expiry. Items of a chunk that
+    /// share an expiry are written with one `MSET`.
+    fn pipelined_multi_set_with_item_expiry<
+        K: ToRedisArgs + Sync + Send,
+        V: ToRedisArgs + Sync + Send,
+    >(
+        &self,
+        items: &[(K, V, Expiry)],
+        call: &CallOptions,
+    ) -> impl Future<Output = WriteOutcome> {
+        self.pipelined_item_write(PipelinedItemWrite::MultiSetWithItemExpiry, items, call)
+    }
+
+    /// Like `pipelined_set_with_expiry`, with each item's own expiry.
+    fn pipelined_set_with_item_expiry<
+        K: ToRedisArgs + Sync + Send,
+        V: ToRedisArgs + Sync + Send,
+    >(
+        &self,
+        items: &[(K, V, Expiry)],
+        call: &CallOptions,
+    ) -> impl Future<Output = WriteOutcome> {
+        self.pipelined_item_write(PipelinedItemWrite::SetWithItemExpiry, items, call)
+    }
+
+    /// Like `pipelined_set_with_expiry_manual`, with each item's own expiry.
+    fn pipelined_set_with_item_expiry_manual<
+        K: ToRedisArgs + Sync + Send,
+        V: ToRedisArgs + Sync + Send,
+    >(
+        &self,
+        items: &[(K, V, Expiry)],
+        call: &CallOptions,
+    ) -> impl Future<Output = WriteOutcome> {
+        self.pipelined_item_write(PipelinedItemWrite::SetWithItemExpiryManual, items, call)
+    }
 
     fn server_adder(&self) -> String;
 }
@@ -51,130 +158,252 @@ pub trait AsyncRedisClient {
 pub struct AsyncRedisClientV1 {
     pub conn_info: ConnectionInfo,
     pub conn: ConnectionManager,
-    batch_size: usize,
+    config: ClientConfig,
 }
 
 impl AsyncRedisClientV1 {
     pub async fn new(conn_info: ConnectionInfo, batch_size: usize) -> RedisResult<Self> {
+        Self::new_with_timeouts(conn_info, batch_size, Timeouts::default()).await
+    }
+
+    /// Creates a client whose connection applies the connect and response limits of `timeouts`.
+    pub async fn new_with_timeouts(
+        conn_info: ConnectionInfo,
+        batch_size: usize,
+        timeouts: Timeouts,
+    ) -> RedisResult<Self> {
         let client = redis::Client::open(conn_info.clone())?;
-        let conn = ConnectionManager::new(client).await?;
+        let conn = ConnectionManager::new_with_config(client, timeouts.manager_config()).await?;
         Ok(Self {
             conn_info,
             conn,
-            batch_size,
+            config: ClientConfig::new(batch_size, timeouts),
         })
     }
-}
-impl AsyncRedisClient for AsyncRedisClientV1 {
-    async fn ping(&self) -> RedisResult<String> {
-        self.conn.clone().ping().await
-    }
-
-    async fn multi_get(&self, keys: Vec<String>) -> RedisResult<Vec<Option<String>>> {
-        self.conn.clone().mget(keys).await
-    }
 
-    async fn multi_set<K: ToRedisArgs + Sync + Send, V: ToRedisArgs + Sync + Send>(
+    /// Executes the chunks one by one and stops at the first failure; the remaining chunks are
+    /// reported as not attempted.
+    async fn execute_pipelines<T: ChunkItem + Sync>(
         &self,
-        items: &[(K, V)],
-    ) -> RedisResult<()> {
-        if items.len() > self.batch_size {
-            for chunk in items.chunks(self.batch_size) {
-                self.conn
-                    .clone()
-                    .mset::<K, V, ()>(chunk)
-                    .await
-                    .map_err(|e| {
-                        info!("Failed to sync {} features: {}", chunk.len(), e);
-                        e
-                    })?;
+        items: &[T],
+        cost: FrameCost,
+        build_pipeline: impl Fn(&[T]) -> Pipeline,
+        method: &'static str,
+        context: &'static str,
+        call: &CallOptions,
+    ) -> WriteOutcome {
+        let gate = self.config.gate(call);
+        let mut outcome = WriteOutcome::default();
+        let chunks: Vec<_> = self.config.chunking.split(items, cost).collect();
+        record_chunks(chunks.len());
+        for (index, (range, chunk)) in chunks.into_iter().enumerate() {
+            if !outcome.failed.is_empty() {
+                outcome.not_attempted.push(range);
+                continue;
+            }
+            if let Some(stop) = gate.closed() {
+                outcome.record_stopped(range, stop);
+                continue;
             }
-            Ok(())
-        } else {
-            self.conn.clone().mset::<K, V, ()>(items).await
+            debug!("Executing {} pipeline with {} items", context, chunk.len());
+            let now = Instant::now();
+            let mut pipeline = build_pipeline(chunk);
+            if self.config.transactions {
+                pipeline.atomic();
+            }
+            let pipeline = &pipeline;
+
+            let result = instrument_chunk(
+                self.config.metrics.as_ref(),
+                method,
+                context,
+                trace_chunk(
+                    index,
+                    chunk.len(),
+                    self.config
+                        .retry_policy
+                        .retry_until(context, gate.deadline(), || async move {
+                            let mut conn = self.conn.clone();
+                            exec_with_eval_fallback(pipeline, &mut conn).await
+                        }),
+                ),
+            )
+            .await;
+            match &result {
+                Ok(()) => debug!(
+                    "Executed pipeline with {} items in {} ms",
+                    chunk.len(),
+                    now.elapsed().as_millis()
+                ),
+                Err(e) => warn!(
+                    "Failed to sync {} features via {}: {}",
+                    chunk.len(),
+                    context,
+                    e
+                ),
+            }
+            outcome.record(range, result);
         }
+        outcome.finish()
     }
 
-    async fn pipelined_multi_set_with_expiry<
+    /// Writes each item with `ttl` only while its key still holds the expected value. Each chunk
+    /// is checked under `WATCH` and written in `MULTI`/`EXEC`, on a connection opened for the call;
+    /// chunks with a mismatching or concurrently changed key are reported as `aborted`.
+    pub async fn compare_and_set_with_expiry<
         K: ToRedisArgs + Sync + Send,
         V: ToRedisArgs + Sync + Send,
     >(
         &self,
-        items: &[(K, V)],
+        items: &[CompareAndSet<K, V>],
         ttl: Duration,
-    ) -> RedisResult<()> {
-        for chunk in items.chunks(self.batch_size) {
-            debug!("Executing mset+expire pipeline with {} items", chunk.len());
-            let now = Instant::now();
+        call: &CallOptions,
+    ) -> WriteOutcome {
+        const METHOD: &str = "compare_and_set_with_expiry";
+        let span = self
+            .config
+            .call_span(METHOD, &self.server_adder(), items.len(), Some(ttl));
+        let write = compare_and_set(&self.conn_info, &self.config, items, ttl, call, METHOD);
+        self.config.write(span, METHOD, items, write).await
+    }
+}
 
-            let pipeline = build_mset_with_expire_pipeline(chunk, Utc::now(), ttl);
-
-            let mut conn = self.conn.clone();
-            pipeline.exec_async(&mut conn).await.map_err(|e| {
-                warn!("Failed to sync {} features: {}", chunk.len(), e);
-                e
-            })?;
-            debug!(
-                "Executed pipeline with {} items in {} ms",
-                chunk.len(),
-                now.elapsed().as_millis()
-            );
-        }
-        Ok(())
+impl ConfigureClient for AsyncRedisClientV1 {
+    fn config_mut(&mut self) -> &mut ClientConfig {
+        &mut self.config
     }
+}
 
-    async fn pipelined_set_with_expiry<
-        K: ToRedisArgs + Sync + Send,
-        V: ToRedisArgs + Sync + Send,
-    >(
+impl AsyncRedisClient for AsyncRedisClientV1 {
+    async fn ping(&self) -> RedisResult<String> {
+        let span = call_span("ping", &self.server_adder(), 0, None, None);
+        let mut conn = self.conn.clone();
+        self.config.read(span, "ping", conn.ping()).await
+    }
+
+    async fn multi_get<K: ToRedisArgs + Sync + Send, V: FromRedisValue + Send>(
+        &self,
+        keys: &[K],
+        call: &CallOptions,
+    ) -> RedisResult<Vec<Option<V>>> {
+        let span = self
+            .config
+            .call_span("multi_get", &self.server_adder(), keys.len(), None);
+        let read = async {
+            let gate = self.config.gate(call);
+            let chunks: Vec<_> = self.config.chunking.split_keys(keys).collect();
+            record_chunks(chunks.len());
+            let mut values = Vec::with_capacity(keys.len());
+            for (index, (range, chunk)) in chunks.into_iter().enumerate() {
+                if let Some(stop) = gate.closed() {
+                    return Err(stop.error(keys.len() - range.start));
+                }
+                let chunk_values: Vec<Option<V>> = instrument_chunk(
+                    self.config.metrics.as_ref(),
+                    "multi_get",
+                    "mget",
+                    trace_chunk(
+                        index,
+                        chunk.len(),
+                        redis::cmd("MGET")
+                            .arg(chunk)
+                            .query_async(&mut self.conn.clone()),
+                    ),
+                )
+                .await?;
+                values.extend(chunk_values);
+            }
+            Ok(values)
+        };
+        self.config.read(span, "multi_get", read).await
+    }
+
+    async fn multi_set<K: ToRedisArgs + Sync + Send, V: ToRedisArgs + Sync + Send>(
         &self,
         items: &[(K, V)],
-        ttl: Duration,
-    ) -> RedisResult<()> {
-        for chunk in items.chunks(self.batch_size) {
-            debug!("Executing per-key set pipeline with {} items", chunk.len());
-            let now = Instant::now();
-            let pipeline = build_set_with_expiry_pipeline(chunk, Utc::now(), ttl);
-
-            let mut conn = self.conn.clone();
-            pipeline.exec_async(&mut conn).await.map_err(|e| {
-                warn!("Failed to sync {} features: {}", chunk.len(), e);
-                e
-            })?;
-            debug!(
-                "Executed pipeline with {} items in {} ms",
-                chunk.len(),
-                now.elapsed().as_millis()
-            );
-        }
-        Ok(())
+        call: &CallOptions,
+    ) -> WriteOutcome {
+        let span = self
+            .config
+            .call_span("multi_set", &self.server_adder(), items.len(), None);
+        let write = async {
+            let gate = self.config.gate(call);
+            let mut outcome = WriteOutcome::default();
+            let chunks: Vec<_> = self.config.chunking.split(items, FrameCost::MSET).collect();
+            record_chunks(chunks.len());
+            for (index, (range, chunk)) in chunks.into_iter().enumerate() {
+                if !outcome.failed.is_empty() {
+                    outcome.not_attempted.push(range);
+                    continue;
+                }
+                if let Some(stop) = gate.closed() {
+                    outcome.record_stopped(range, stop);
+                    continue;
+                }
+                let result = instrument_chunk(
+                    self.config.metrics.as_ref(),
+                    "multi_set",
+                    "mset",
+                    trace_chunk(
+                        index,
+                        chunk.len(),
+                        self.config.retry_policy.retry_until(
+                            "mset",
+                            gate.deadline(),
+                            || async move { self.conn.clone().mset::<K, V, ()>(chunk).await },
+                        ),
+                    ),
+                )
+                .await;
+                if let Err(e) = &result {
+                    info!("Failed to sync {} features: {}", chunk.len(), e);
+                }
+                outcome.record(range, result);
+            }
+            outcome.finish()
+        };
+        self.config.write(span, "multi_set", items, write).await
     }
 
-    async fn pipelined_set_with_expiry_manual<
-        K: ToRedisArgs + Sync + Send,
-        V: ToRedisArgs + Sync + Send,
-    >(
+    async fn pipelined_write<K: ToRedisArgs + Sync + Send, V: ToRedisArgs + Sync + Send>(
         &self,
+        kind: PipelinedWrite,
         items: &[(K, V)],
         ttl: Duration,
-    ) -> RedisResult<()> {
-        for chunk in items.chunks(self.batch_size) {
-            debug!("Executing per-key set pipeline with {} items", chunk.len());
-            let now = Instant::now();
-            let pipeline = build_set_with_expiry_manual_pipeline(chunk, Utc::now(), ttl);
-
-            let mut conn = self.conn.clone();
-            pipeline.exec_async(&mut conn).await.map_err(|e| {
-                warn!("Failed to sync {} features: {}", chunk.len(), e);
-                e
-            })?;
-            debug!(
-                "Executed pipeline with {} items in {} ms",
-                chunk.len(),
-                now.elapsed().as_millis()
-            );
-        }
-        Ok(())
+        call: &CallOptions,
+    ) -> WriteOutcome {
+        let span =
+            self.config
+                .call_span(kind.method(), &self.server_adder(), items.len(), Some(ttl));
+        let write = self.execute_pipelines(
+            items,
+            self.config.frame_cost(kind, ttl),
+            |chunk| self.config.pipeline(kind, chunk, ttl),
+            kind.method(),
+            kind.context(),
+            call,
+        );
+        self.config.write(span, kind.method(), items, write).await
+    }
+
+    async fn pipelined_item_write<K: ToRedisArgs + Sync + Send, V: ToRedisArgs + Sync + Send>(
+        &self,
+        kind: PipelinedItemWrite,
+        items: &[(K, V, Expiry)],
+        call: &CallOptions,
+    ) -> WriteOutcome {
+        let span = self
+            .config
+            .call_span(kind.method(), &self.server_adder(), items.len(), None);
+        let write = self.execute_pipelines(
+            items,
+            self.config.item_frame_cost(kind),
+            |chunk| self.config.item_pipeline(kind, chunk),
+            kind.method(),
+            kind.context(),
+            call,
+        );
+        self.config.write(span, kind.method(), items, write).await
     }
 
     fn server_adder(&self) -> String {
@@ -183,251 +412,822 @@ impl AsyncRedisClient for AsyncRedisClientV1 {
 }
 
 struct ChunkCommand {
-    len: usize,
-    cmd: redis::Cmd,
+    range: Range<usize>,
+    cmd: Arc<redis::Cmd>,
 }
 
 pub struct AsyncRedisClientPooled {
     pub conn_info: ConnectionInfo,
-    pub conn: ConnectionManager,
-    batch_size: usize,
+    config: ClientConfig,
     write_parallelism: usize,
-    write_connection_pool_size: usize,
-    write_connections: Vec<Mutex<MultiplexedConnection>>,
-    next_id: AtomicUsize,
+    write_pool: Arc<ConnectionPool>,
+    read_parallelism: usize,
+    read_pool: Arc<ConnectionPool>,
+    adaptive: Option<AdaptiveController>,
 }
 
 impl AsyncRedisClientPooled {
+    /// Creates a client whose read pool has the same parallelism and size as the write pool.
     pub async fn new(
         conn_info: ConnectionInfo,
         batch_size: usize,
         write_parallelism: usize,
         write_connection_pool_size: usize,
     ) -> RedisResult<Self> {
-        let parallelism = write_parallelism.max(1);
-        let pool_size = write_connection_pool_size.max(parallelism).max(1);
-        let client = redis::Client::open(conn_info.clone())?;
-        let conn = ConnectionManager::new(client.clone()).await?;
-        let mut pooled_conns = Vec::with_capacity(pool_size);
+        Self::new_with_read_pool(
+            conn_info,
+            batch_size,
+            write_parallelism,
+            write_connection_pool_size,
+            write_parallelism,
+            write_connection_pool_size,
+        )
+        .await
+    }
 
-        for _ in 0..pool_size {
-            let connection = client.get_multiplexed_async_connection().await?;
-            pooled_conns.push(Mutex::new(connection));
-        }
+    pub async fn new_with_read_pool(
+        conn_info: ConnectionInfo,
+        batch_size: usize,
+        write_parallelism: usize,
+        write_connection_pool_size: usize,
+        read_parallelism: usize,
+        read_connection_pool_size: usize,
+    ) -> RedisResult<Self> {
+        Self::new_with_pools(
+            conn_info,
+            batch_size,
+            write_parallelism,
+            PoolOptions::with_size(write_connection_pool_size),
+            read_parallelism,
+            PoolOptions::with_size(read_connection_pool_size),
+        )
+        .await
+    }
+
+    /// Creates a client with independently configured write and read pools. Reads and `ping` use
+    /// the read pool, writes the write pool.
+    pub async fn new_with_pools(
+        conn_info: ConnectionInfo,
+        batch_size: usize,
+        write_parallelism: usize,
+        write_pool: PoolOptions,
+        read_parallelism: usize,
+        read_pool: PoolOptions,
+    ) -> RedisResult<Self> {
+        let write_parallelism = write_parallelism.max(1);
+        let read_parallelism = read_parallelism.max(1);
+        let timeouts = write_pool.timeouts;
+        let client = redis::Client::open(conn_info.clone())?;
+        let write_pool =
+            ConnectionPool::new(&client, fit_pool(write_pool, write_parallelism)).await?;
+        let read_pool = ConnectionPool::new(&client, fit_pool(read_pool, read_parallelism)).await?;
 
         Ok(Self {
             conn_info,
-            conn,
-            batch_size,
-            write_parallelism: parallelism,
-            write_connection_pool_size: pool_size,
-            write_connections: pooled_conns,
-            next_id: AtomicUsize::new(0),
+            config: ClientConfig::new(batch_size, timeouts),
+            write_parallelism,
+            write_pool,
+            read_parallelism,
+            read_pool,
+            adaptive: None,
         })
     }
 
-    async fn with_next_connection<T, F>(&self, execute_fn: F) -> RedisResult<T>
-    where
-        for<'a> F: FnOnce(&'a mut MultiplexedConnection) -> BoxFuture<'a, RedisResult<T>>,
-    {
-        let start = self.next_id.fetch_add(1, Ordering::SeqCst);
-        let pool_size = self.write_connection_pool_size;
+    /// Starts background health checks of the read and write pools. They stop when the client is
+    /// dropped.
+    pub fn with_health_check(self, health_check: HealthCheck) -> Self {
+        self.write_pool.spawn_health_check(health_check);
+        self.read_pool.spawn_health_check(health_check);
+        self
+    }
 
-        for offset in 0..pool_size {
-            let idx = (start + offset) % pool_size;
-            if let Ok(mut conn_guard) = self.write_connections[idx].try_lock() {
-                return execute_fn(&mut conn_guard).await;
-            }
+    /// Lets an AIMD controller tune the chunk size and parallelism of writes within `options`,
+    /// starting from the client's current values, from the latency and errors of the chunks it
+    /// writes. The maximum parallelism is capped at what the write pool can serve. Reads and
+    /// `compare_and_set_with_expiry` keep the static chunking.
+    pub fn with_adaptive(mut self, mut options: AdaptiveOptions) -> Self {
+        options.max_parallelism = options.max_parallelism.min(self.write_pool.capacity());
+        let initial = AdaptiveSettings {
+            batch_size: self
+                .config
+                .chunking
+                .max_items()
+                .unwrap_or(options.max_batch_size),
+            parallelism: self.write_parallelism,
+        };
+        let adaptive = AdaptiveController::new(options, initial);
+        if let Some(metrics) = &self.config.metrics {
+            metrics.record_adaptive(adaptive.settings());
         }
+        self.adaptive = Some(adaptive);
+        self
+    }
+
+    /// Chunk size and write parallelism currently chosen by the adaptive controller, if any.
+    pub fn adaptive_settings(&self) -> Option<AdaptiveSettings> {
+        self.adaptive.as_ref().map(AdaptiveController::settings)
+    }
+
+    fn write_chunking(&self) -> ChunkingStrategy {
+        match &self.adaptive {
+            Some(adaptive) => self
+                .config
+                .chunking
+                .with_max_items(adaptive.settings().batch_size),
+            None => self.config.chunking,
+        }
+    }
+
+    fn write_parallelism(&self) -> usize {
+        match &self.adaptive {
+            Some(adaptive) => adaptive.settings().parallelism,
+            None => self.write_parallelism,
+        }
+    }
 
-        let idx = start % pool_size;
-        let mut conn_guard = self.write_connections[idx].lock().await;
-        execute_fn(&mut conn_guard).await
+    /// Span of a write call, with the chunk size the adaptive controller currently uses.
+    fn write_span(&self, method: &'static str, items: usize, ttl: Option<Duration>) -> Span {
+        let batch_size = self.write_chunking().max_items();
+        call_span(method, &self.server_adder(), items, batch_size, ttl)
     }
 
+    /// Feeds a written chunk to the adaptive controller, if any.
+    fn observe_chunk(&self, latency: Duration, ok: bool) {
+        let Some(settings) = self
+            .adaptive
+            .as_ref()
+            .and_then(|adaptive| adaptive.observe(latency, ok))
+        else {
+            return;
+        };
+        debug!(
+            "Adaptive controller set batch size to {} and write parallelism to {}",
+            settings.batch_size, settings.parallelism
+        );
+        if let Some(metrics) = &self.config.metrics {
+            metrics.record_adaptive(settings);
+        }
+    }
+
+    /// Writes `pipelines` with up to `write_parallelism` in flight.
     async fn execute_pipelines(
         &self,
-        pipelines: Vec<(usize, Pipeline)>,
+        pipelines: Vec<(Range<usize>, Pipeline)>,
+        method: &'static str,
         context: &'static str,
-    ) -> RedisResult<()> {
+        call: &CallOptions,
+    ) -> WriteOutcome {
+        let gate = &self.config.gate(call);
+        let mut outcome = WriteOutcome::default();
+        record_chunks(pipelines.len());
         if pipelines.is_empty() {
-            return Ok(());
+            return outcome;
         }
 
-        let mut tasks = stream::iter(pipelines.into_iter().map(
-            move |(chunk_len, pipeline)| async move {
+        let mut tasks = stream::iter(pipelines.into_iter().enumerate().map(
+            move |(index, (range, pipeline))| async move {
+                if let Some(stop) = gate.closed() {
+                    return (range, Instant::now(), Err(stop));
+                }
                 let started = Instant::now();
                 let result = self
-                    .with_next_connection(move |conn| {
-                        Box::pin(async move { pipeline.exec_async(conn).await })
-                    })
+                    .write_chunk(index, range.len(), pipeline, method, context, gate)
                     .await;
-                (chunk_len, started, result)
+                (range, started, Ok(result))
             },
         ))
-        .buffer_unordered(self.write_parallelism);
+        .buffer_unordered(self.write_parallelism());
 
-        let mut first_error: Option<redis::RedisError> = None;
-
-        while let Some((chunk_len, started, result)) = tasks.next().await {
-            match result {
+        while let Some((range, started, result)) = tasks.next().await {
+            let result = match result {
+                Ok(result) => result,
+                Err(stop) => {
+                    outcome.record_stopped(range, stop);
+                    continue;
+                }
+            };
+            match &result {
                 Ok(()) => {
                     debug!(
                         "Executed pipeline with {} items in {} ms",
-                        chunk_len,
+                        range.len(),
                         started.elapsed().as_millis()
                     );
                 }
                 Err(err) => {
                     warn!(
                         "Failed to sync {} features via {}: {}",
-                        chunk_len, context, err
+                        range.len(),
+                        context,
+                        err
                     );
-                    if first_error.is_none() {
-                        first_error = Some(err);
-                    }
                 }
             }
+            outcome.record(range, result);
         }
 
-        if let Some(err) = first_error {
-            Err(err)
-        } else {
-            Ok(())
+        outcome.finish()
+    }
+
+    /// Writes chunk `index` of `items` items on the write pool, retrying it per the retry policy
+    /// until the deadline of `gate`, and feeds the adaptive controller.
+    async fn write_chunk(
+        &self,
+        index: usize,
+        items: usize,
+        pipeline: Pipeline,
+        method: &'static str,
+        context: &'static str,
+        gate: &ChunkGate,
+    ) -> RedisResult<()> {
+        let started = Instant::now();
+        let pipeline = Arc::new(pipeline);
+        let result = instrument_chunk(
+            self.config.metrics.as_ref(),
+            method,
+            context,
+            trace_chunk(
+                index,
+                items,
+                self.config
+                    .retry_policy
+                    .retry_until(context, gate.deadline(), || {
+                        let pipeline = pipeline.clone();
+                        self.write_pool.with_next_connection(method, move |conn| {
+                            Box::pin(async move { exec_with_eval_fallback(&pipeline, conn).await })
+                        })
+                    }),
+            ),
+        )
+        .await;
+        self.observe_chunk(started.elapsed(), result.is_ok());
+        result
+    }
+
+    /// Builds the chunks of `items`, written with frames of `cost`, and writes them.
+    async fn write_items<T: ChunkItem>(
+        &self,
+        items: &[T],
+        cost: FrameCost,
+        build_pipeline: impl Fn(&[T]) -> Pipeline,
+        method: &'static str,
+        context: &'static str,
+        call: &CallOptions,
+    ) -> WriteOutcome {
+        let pipelines = self
+            .write_chunking()
+            .split(items, cost)
+            .map(|(range, chunk)| {
+                debug!("Executing {} pipeline with {} items", context, chunk.len());
+                let mut pipeline = build_pipeline(chunk);
+                if self.config.transactions {
+                    pipeline.atomic();
+                }
+                (range, pipeline)
+            })
+            .collect();
+        self.execute_pipelines(pipelines, method, context, call)
+            .await
+    }
+
+    /// Writes each item with `ttl` only while its key still holds the expected value. Each chunk
+    /// is checked under `WATCH` and written in `MULTI`/`EXEC`, on a connection opened for the call;
+    /// chunks with a mismatching or concurrently changed key are reported as `aborted`.
+    pub async fn compare_and_set_with_expiry<
+        K: ToRedisArgs + Sync + Send,
+        V: ToRedisArgs + Sync + Send,
+    >(
+        &self,
+        items: &[CompareAndSet<K, V>],
+        ttl: Duration,
+        call: &CallOptions,
+    ) -> WriteOutcome {
+        const METHOD: &str = "compare_and_set_with_expiry";
+        let span = self
+            .config
+            .call_span(METHOD, &self.server_adder(), items.len(), Some(ttl));
+        let write = compare_and_set(&self.conn_info, &self.config, items, ttl, call, METHOD);
+        self.config.write(span, METHOD, items, write).await
+    }
+
+    /// Writes `items` with `MSET` as the stream yields them, without collecting them first.
+    ///
+    /// Chunks are formed on the fly by the client's chunking strategy and up to
+    /// `write_parallelism` of them are written at once. The stream is not polled while that many
+    /// are in flight, so memory stays bounded however many items it yields. Ranges in the outcome
+    /// are positions in the stream. Once the call's deadline passes, the call is cancelled or the
+    /// client is shut down, the chunk about to be sent is reported as not attempted and the rest of
+    /// the stream is left unconsumed.
+    pub async fn multi_set_stream<K: ToRedisArgs + Sync + Send, V: ToRedisArgs + Sync + Send>(
+        &self,
+        items: impl Stream<Item = (K, V)>,
+        call: &CallOptions,
+    ) -> WriteOutcome {
+        self.write_stream(
+            items,
+            self.config.transaction_cost(FrameCost::MSET),
+            |chunk| {
+                let mut pipeline = redis::pipe();
+                let cmd = pipeline.cmd("MSET");
+                for (k, v) in chunk {
+                    cmd.arg(k).arg(v);
+                }
+                pipeline
+            },
+            "multi_set_stream",
+            "mset",
+            None,
+            call,
+        )
+        .await
+    }
+
+    /// `multi_set_stream` with `ttl`, writing each chunk like `pipelined_multi_set_with_expiry`.
+    pub async fn pipelined_multi_set_with_expiry_stream<
+        K: ToRedisArgs + Sync + Send,
+        V: ToRedisArgs + Sync + Send,
+    >(
+        &self,
+        items: impl Stream<Item = (K, V)>,
+        ttl: Duration,
+        call: &CallOptions,
+    ) -> WriteOutcome {
+        self.write_stream(
+            items,
+            self.config
+                .frame_cost(PipelinedWrite::MultiSetWithExpiry, ttl),
+            |chunk| {
+                self.config
+                    .pipeline(PipelinedWrite::MultiSetWithExpiry, chunk, ttl)
+            },
+            "pipelined_multi_set_with_expiry_stream",
+            "mset+expire",
+            Some(ttl),
+            call,
+        )
+        .await
+    }
+
+    /// Writes `items` in chunks built by `build_pipeline` as the stream yields them, each in
+    /// `MULTI`/`EXEC` when the client uses transactions.
+    #[allow(clippy::too_many_arguments)]
+    async fn write_stream<T: ChunkItem>(
+        &self,
+        items: impl Stream<Item = T>,
+        cost: FrameCost,
+        build_pipeline: impl Fn(&[T]) -> Pipeline,
+        method: &'static str,
+        context: &'static str,
+        ttl: Option<Duration>,
+        call: &CallOptions,
+    ) -> WriteOutcome {
+        let span = self.write_span(method, 0, ttl);
+        let write = async {
+            let gate = &self.config.gate(call);
+            let count_bytes = self.config.metrics.is_some();
+            let mut stopped = None;
+            let mut outcome = WriteOutcome::default();
+            let (mut chunks, mut seen, mut bytes) = (0, 0, 0);
+            {
+                let chunking = self.write_chunking();
+                let tasks = chunking
+                    .chunk_stream(items, cost)
+                    .take_while(|(range, _)| {
+                        stopped = gate.closed().map(|stop| (range.clone(), stop));
+                        future::ready(stopped.is_none())
+                    })
+                    .enumerate()
+                    .map(|(index, (range, chunk))| {
+                        debug!("Executing {} pipeline with {} items", context, chunk.len());
+                        let bytes: usize = match count_bytes {
+                            true => chunk.iter().map(ChunkItem::resp_len).sum(),
+                            false => 0,
+                        };
+                        let mut pipeline = build_pipeline(&chunk);
+                        if self.config.transactions {
+                            pipeline.atomic();
+                        }
+                        async move {
+                            let result = self
+                                .write_chunk(index, range.len(), pipeline, method, context, gate)
+                                .await;
+                            (range, bytes, result)
+                        }
+                    })
+                    .buffer_unordered(self.write_parallelism());
+                let mut tasks = pin!(tasks);
+                while let Some((range, chunk_bytes, result)) = tasks.next().await {
+                    chunks += 1;
+                    seen = seen.max(range.end);
+                    match &result {
+                        Ok(()) => bytes += chunk_bytes,
+                        Err(err) => warn!(
+                            "Failed to sync {} features via {}: {}",
+                            range.len(),
+                            context,
+                            err
+                        ),
+                    }
+                    outcome.record(range, result);
+                }
+            }
+            if let Some((range, stop)) = stopped {
+                seen = seen.max(range.end);
+                outcome.record_stopped(range, stop);
+            }
+            record_chunks(chunks);
+            Span::current().record("items", seen);
+            (outcome.finish(), bytes)
+        };
+        trace_write(
+            span,
+            instrument_counted_write(self.config.metrics.as_ref(), method, write),
+        )
+        .await
+    }
+
+    /// Shuts the client down gracefully: calls stop starting new chunks, with
+    /// `WriteOutcome::shut_down` set, and once the calls in flight have finished their chunks, the
+    /// write and read pools are closed. Calls made after `shutdown()` report all of their chunks as
+    /// not attempted.
+    pub async fn shutdown(&self) {
+        self.config.shutdown.cancel();
+        self.config.calls.close();
+        self.config.calls.wait().await;
+        self.write_pool.close().await;
+        self.read_pool.close().await;
+        debug!("Shut down client for {}", self.server_adder());
+    }
+}
+
+impl ConfigureClient for AsyncRedisClientPooled {
+    fn config_mut(&mut self) -> &mut ClientConfig {
+        &mut self.config
+    }
+
+    /// Records the client's calls, including the time spent waiting for pooled connections and the
+    /// settings of the adaptive controller, in `metrics`, labelled with `server_adder()`.
+    fn with_metrics(mut self, metrics: Metrics) -> Self {
+        let client_metrics = metrics.for_server(self.server_adder());
+        self.write_pool.set_metrics(client_metrics.clone(), "write");
+        self.read_pool.set_metrics(client_metrics.clone(), "read");
+        if let Some(settings) = self.adaptive_settings() {
+            client_metrics.record_adaptive(settings);
         }
+        self.config.metrics = Some(client_metrics);
+        self
     }
 }
 
+/// Raises `max_size` so that the pool can serve `parallelism` calls at once. `min_size` is left
+/// alone, so a small pool grows on demand rather than being forced up front.
+fn fit_pool(mut options: PoolOptions, parallelism: usize) -> PoolOptions {
+    let per_connection = options.max_in_flight_per_connection.max(1);
+    options.max_size = options.max_size.max(parallelism.div_ceil(per_connection));
+    options
+}
+
 impl AsyncRedisClient for AsyncRedisClientPooled {
     async fn ping(&self) -> RedisResult<String> {
-        self.conn.clone().ping().await
+        let span = call_span("ping", &self.server_adder(), 0, None, None);
+        let ping = self
+            .read_pool
+            .with_next_connection("ping", |conn| Box::pin(async move { conn.ping().await }));
+        self.config.read(span, "ping", ping).await
     }
 
-    async fn multi_get(&self, keys: Vec<String>) -> RedisResult<Vec<Option<String>>> {
-        self.conn.clone().mget(keys).await
+    async fn multi_get<K: ToRedisArgs + Sync + Send, V: FromRedisValue + Send>(
+        &self,
+        keys: &[K],
+        call: &CallOptions,
+    ) -> RedisResult<Vec<Option<V>>> {
+        let span = self
+            .config
+            .call_span("multi_get", &self.server_adder(), keys.len(), None);
+        let commands: Vec<(Range<usize>, Arc<redis::Cmd>)> = self
+            .config
+            .chunking
+            .split_keys(keys)
+            .map(|(range, chunk)| {
+                let mut cmd = redis::cmd("MGET");
+                cmd.arg(chunk);
+                (range, Arc::new(cmd))
+            })
+            .collect();
+
+        // `buffered` keeps the chunks in input order, so the values line up with `keys`.
+        let gate = &self.config.gate(call);
+        let chunks = commands.len();
+        let read = stream::iter(commands.into_iter().enumerate().map(
+            |(index, (range, cmd))| async move {
+                if let Some(stop) = gate.closed() {
+                    return Err(stop.error(keys.len() - range.start));
+                }
+                instrument_chunk(
+                    self.config.metrics.as_ref(),
+                    "multi_get",
+                    "mget",
+                    trace_chunk(
+                        index,
+                        range.len(),
+                        self.read_pool
+                            .with_next_connection("multi_get", move |conn| {
+                                Box::pin(
+                                    async move { cmd.query_async::<Vec<Option<V>>>(conn).await },
+                                )
+                            }),
+                    ),
+                )
+                .await
+            },
+        ))
+        .buffered(self.read_parallelism)
+        .try_collect::<Vec<Vec<Option<V>>>>();
+        let read = async {
+            record_chunks(chunks);
+            read.await
+        };
+        let chunk_values = self.config.read(span, "multi_get", read).await?;
+
+        Ok(chunk_values.into_iter().flatten().collect())
     }
 
     async fn multi_set<K: ToRedisArgs + Sync + Send, V: ToRedisArgs + Sync + Send>(
         &self,
         items: &[(K, V)],
-    ) -> RedisResult<()> {
-        if items.is_empty() {
-            return Ok(());
-        }
-
-        let commands: Vec<ChunkCommand> = items
-            .chunks(self.batch_size)
-            .map(|chunk| {
+        call: &CallOptions,
+    ) -> WriteOutcome {
+        let span = self.write_span("multi_set", items.len(), None);
+        let commands: Vec<ChunkCommand> = self
+            .write_chunking()
+            .split(items, FrameCost::MSET)
+            .map(|(range, chunk)| {
                 let mut cmd = redis::cmd("MSET");
                 for (k, v) in chunk {
                     cmd.arg(k);
                     cmd.arg(v);
                 }
                 ChunkCommand {
-                    len: chunk.len(),
-                    cmd,
+                    range,
+                    cmd: Arc::new(cmd),
                 }
             })
             .collect();
 
-        let mut tasks = stream::iter(commands.into_iter().map(move |chunk| async move {
-            let ChunkCommand {
-                len: chunk_len,
-                cmd,
-            } = chunk;
-            let result = self
-                .with_next_connection(move |conn| {
-                    Box::pin(async move { cmd.query_async::<()>(conn).await })
-                })
+        let gate = &self.config.gate(call);
+        let chunks = commands.len();
+        let mut tasks = stream::iter(commands.into_iter().enumerate().map(
+            move |(index, chunk)| async move {
+                let ChunkCommand { range, cmd } = chunk;
+                if let Some(stop) = gate.closed() {
+                    return (range, Err(stop));
+                }
+                let started = Instant::now();
+                let result = instrument_chunk(
+                    self.config.metrics.as_ref(),
+                    "multi_set",
+                    "mset",
+                    trace_chunk(
+                        index,
+                        range.len(),
+                        self.config
+                            .retry_policy
+                            .retry_until("mset", gate.deadline(), || {
+                                let cmd = cmd.clone();
+                                self.write_pool
+                                    .with_next_connection("multi_set", move |conn| {
+                                        Box::pin(async move { cmd.query_async::<()>(conn).await })
+                                    })
+                            }),
+                    ),
+                )
                 .await;
-            (chunk_len, result)
-        }))
-        .buffer_unordered(self.write_parallelism);
-
-        let mut first_error: Option<redis::RedisError> = None;
+                self.observe_chunk(started.elapsed(), result.is_ok());
+                (range, Ok(result))
+            },
+        ))
+        .buffer_unordered(self.write_parallelism());
 
-        while let Some((chunk_len, result)) = tasks.next().await {
-            if let Err(err) = result {
-                info!("Failed to sync {} features: {}", chunk_len, err);
-                if first_error.is_none() {
-                    first_error = Some(err);
+        let write = async {
+            let mut outcome = WriteOutcome::default();
+            record_chunks(chunks);
+            while let Some((range, result)) = tasks.next().await {
+                let result = match result {
+                    Ok(result) => result,
+                    Err(stop) => {
+                        outcome.record_stopped(range, stop);
+                        continue;
+                    }
+                };
+                if let Err(err) = &result {
+                    info!("Failed to sync {} features: {}", range.len(), err);
                 }
+                outcome.record(range, result);
             }
-        }
-
-        if let Some(err) = first_error {
-            Err(err)
-        } else {
-            Ok(())
-        }
+            outcome.finish()
+        };
+        self.config.write(span, "multi_set", items, write).await
     }
 
-    async fn pipelined_multi_set_with_expiry<
-        K: ToRedisArgs + Sync + Send,
-        V: ToRedisArgs + Sync + Send,
-    >(
+    async fn pipelined_write<K: ToRedisArgs + Sync + Send, V: ToRedisArgs + Sync + Send>(
         &self,
+        kind: PipelinedWrite,
         items: &[(K, V)],
         ttl: Duration,
-    ) -> RedisResult<()> {
-        let pipelines: Vec<_> = items
-            .chunks(self.batch_size)
-            .map(|chunk| {
-                debug!("Executing mset+expire pipeline with {} items", chunk.len());
-                let pipeline = build_mset_with_expire_pipeline(chunk, Utc::now(), ttl);
-                (chunk.len(), pipeline)
-            })
-            .collect();
-
-        self.execute_pipelines(pipelines, "mset+expire").await
+        call: &CallOptions,
+    ) -> WriteOutcome {
+        let span = self.write_span(kind.method(), items.len(), Some(ttl));
+        let write = self.write_items(
+            items,
+            self.config.frame_cost(kind, ttl),
+            |chunk| self.config.pipeline(kind, chunk, ttl),
+            kind.method(),
+            kind.context(),
+            call,
+        );
+        self.config.write(span, kind.method(), items, write).await
     }
 
-    async fn pipelined_set_with_expiry<
-        K: ToRedisArgs + Sync + Send,
-        V: ToRedisArgs + Sync + Send,
-    >(
+    async fn pipelined_item_write<K: ToRedisArgs + Sync + Send, V: ToRedisArgs + Sync + Send>(
         &self,
-        items: &[(K, V)],
-        ttl: Duration,
-    ) -> RedisResult<()> {
-        let pipelines: Vec<_> = items
-            .chunks(self.batch_size)
-            .map(|chunk| {
-                debug!("Executing per-key set pipeline with {} items", chunk.len());
-                let pipeline = build_set_with_expiry_pipeline(chunk, Utc::now(), ttl);
-                (chunk.len(), pipeline)
-            })
-            .collect();
+        kind: PipelinedItemWrite,
+        items: &[(K, V, Expiry)],
+        call: &CallOptions,
+    ) -> WriteOutcome {
+        let span = self.write_span(kind.method(), items.len(), None);
+        let write = self.write_items(
+            items,
+            self.config.item_frame_cost(kind),
+            |chunk| self.config.item_pipeline(kind, chunk),
+            kind.method(),
+            kind.context(),
+            call,
+        );
+        self.config.write(span, kind.method(), items, write).await
+    }
 
-        self.execute_pipelines(pipelines, "set+expiry").await
+    fn server_adder(&self) -> String {
+        self.conn_info.addr.to_string()
     }
+}
 
-    async fn pipelined_set_with_expiry_manual<
-        K: ToRedisArgs + Sync + Send,
-        V: ToRedisArgs + Sync + Send,
-    >(
-        &self,
-        items: &[(K, V)],
+/// The ways the `pipelined_*_with_expiry*` methods write items that share a TTL.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PipelinedWrite {
+    /// See `build_mset_with_expire_pipeline`.
+    MultiSetWithExpiry,
+    /// See `build_set_with_expiry_pipeline`.
+    SetWithExpiry,
+    /// See `build_set_with_expiry_manual_pipeline`.
+    SetWithExpiryManual,
+    /// See `build_set_with_expiry_script_pipeline`.
+    SetWithExpiryScript,
+}
+
+impl PipelinedWrite {
+    /// Name of the `AsyncRedisClient` method, which labels spans and metrics.
+    pub fn method(self) -> &'static str {
+        match self {
+            PipelinedWrite::MultiSetWithExpiry => "pipelined_multi_set_with_expiry",
+            PipelinedWrite::SetWithExpiry => "pipelined_set_with_expiry",
+            PipelinedWrite::SetWithExpiryManual => "pipelined_set_with_expiry_manual",
+            PipelinedWrite::SetWithExpiryScript => "pipelined_set_with_expiry_script",
+        }
+    }
+
+    /// Commands of a chunk, as logged and recorded in chunk metrics.
+    pub fn context(self) -> &'static str {
+        match self {
+            PipelinedWrite::MultiSetWithExpiry => "mset+expire",
+            PipelinedWrite::SetWithExpiry => "set+expiry",
+            PipelinedWrite::SetWithExpiryManual => "manual set+expiry",
+            PipelinedWrite::SetWithExpiryScript => "script set+expiry",
+        }
+    }
+
+    /// Builds the pipeline of `chunk`.
+    pub fn build<K: ToRedisArgs + Sync + Send, V: ToRedisArgs + Sync + Send>(
+        self,
+        chunk: &[(K, V)],
+        now: DateTime<Utc>,
         ttl: Duration,
-    ) -> RedisResult<()> {
-        let pipelines: Vec<_> = items
-            .chunks(self.batch_size)
-            .map(|chunk| {
-                debug!("Executing per-key set pipeline with {} items", chunk.len());
-                let pipeline = build_set_with_expiry_manual_pipeline(chunk, Utc::now(), ttl);
-                (chunk.len(), pipeline)
-            })
-            .collect();
+        mode: ExpiryMode,
+        jitter: Option<TtlJitter>,
+    ) -> Pipeline {
+        let build = match self {
+            PipelinedWrite::MultiSetWithExpiry => build_mset_with_expire_pipeline,
+            PipelinedWrite::SetWithExpiry => build_set_with_expiry_pipeline,
+            PipelinedWrite::SetWithExpiryManual => build_set_with_expiry_manual_pipeline,
+            PipelinedWrite::SetWithExpiryScript => build_set_with_expiry_script_pipeline,
+        };
+        build(chunk, now, ttl, mode, jitter)
+    }
 
-        self.execute_pipelines(pipelines, "manual set+expiry").await
+    /// Bytes the commands of a chunk add to its keys and values, for TTLs of up to `max_ttl`.
+    pub fn frame_cost(self, mode: ExpiryMode, max_ttl: Duration) -> FrameCost {
+        let expiry_len = resp_bulk_len(decimal_len(mode.value(Utc::now(), max_ttl)));
+        match self {
+            PipelinedWrite::MultiSetWithExpiry => FrameCost {
+                per_item: command_len(mode.expire_command(), 2) + expiry_len,
+                repeats_key: true,
+                ..FrameCost::MSET
+            },
+            PipelinedWrite::SetWithExpiry | PipelinedWrite::SetWithExpiryManual => FrameCost {
+                per_chunk: 0,
+                per_item: set_with_expiry_len(mode, expiry_len),
+                repeats_key: false,
+            },
+            // `EVAL` of the source, as resent when the server lacks the script, outweighs
+            // `EVALSHA` of its hash.
+            PipelinedWrite::SetWithExpiryScript => FrameCost {
+                per_chunk: MAX_ARRAY_HEADER_LEN
+                    + resp_bulk_len("EVAL".len())
+                    + resp_bulk_len(SET_WITH_EXPIRY_LUA.len())
+                    + resp_bulk_len(decimal_len(u32::MAX.into()))
+                    + resp_bulk_len(mode.set_option().len()),
+                per_item: expiry_len,
+                repeats_key: false,
+            },
+        }
     }
+}
 
-    fn server_adder(&self) -> String {
-        self.conn_info.addr.to_string()
+/// Bytes a `SET` with an expiry of `expiry_len` bytes adds to the key and value.
+fn set_with_expiry_len(mode: ExpiryMode, expiry_len: usize) -> usize {
+    command_len("SET", 4) + resp_bulk_len(mode.set_option().len()) + expiry_len
+}
+
+/// The ways the `pipelined_*_with_item_expiry*` methods write items with their own expiries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PipelinedItemWrite {
+    /// See `build_mset_with_item_expiry_pipeline`.
+    MultiSetWithItemExpiry,
+    /// See `build_set_with_item_expiry_pipeline`.
+    SetWithItemExpiry,
+    /// See `build_set_with_item_expiry_manual_pipeline`.
+    SetWithItemExpiryManual,
+}
+
+impl PipelinedItemWrite {
+    /// Name of the `AsyncRedisClient` method, which labels spans and metrics.
+    pub fn method(self) -> &'static str {
+        match self {
+            PipelinedItemWrite::MultiSetWithItemExpiry => "pipelined_multi_set_with_item_expiry",
+            PipelinedItemWrite::SetWithItemExpiry => "pipelined_set_with_item_expiry",
+            PipelinedItemWrite::SetWithItemExpiryManual => "pipelined_set_with_item_expiry_manual",
+        }
+    }
+
+    /// Commands of a chunk, as logged and recorded in chunk metrics.
+    pub fn context(self) -> &'static str {
+        match self {
+            PipelinedItemWrite::MultiSetWithItemExpiry => "mset+expire",
+            PipelinedItemWrite::SetWithItemExpiry => "set+expiry",
+            PipelinedItemWrite::SetWithItemExpiryManual => "manual set+expiry",
+        }
+    }
+
+    /// Builds the pipeline of `chunk`.
+    pub fn build<K: ToRedisArgs + Sync + Send, V: ToRedisArgs + Sync + Send>(
+        self,
+        chunk: &[(K, V, Expiry)],
+        now: DateTime<Utc>,
+        mode: ExpiryMode,
+        jitter: Option<TtlJitter>,
+    ) -> Pipeline {
+        let build = match self {
+            PipelinedItemWrite::MultiSetWithItemExpiry => build_mset_with_item_expiry_pipeline,
+            PipelinedItemWrite::SetWithItemExpiry => build_set_with_item_expiry_pipeline,
+            PipelinedItemWrite::SetWithItemExpiryManual => {
+                build_set_with_item_expiry_manual_pipeline
+            }
+        };
+        build(chunk, now, mode, jitter)
+    }
+
+    /// Bytes the commands of a chunk add to its keys and values. Items carry their own expiries,
+    /// so each is counted at the length of the largest one.
+    pub fn frame_cost(self, mode: ExpiryMode) -> FrameCost {
+        let expiry_len = resp_bulk_len(decimal_len(u64::MAX));
+        match self {
+            PipelinedItemWrite::MultiSetWithItemExpiry => FrameCost {
+                per_item: command_len(mode.expire_command(), 2) + expiry_len,
+                repeats_key: true,
+                ..FrameCost::MSET
+            },
+            PipelinedItemWrite::SetWithItemExpiry | PipelinedItemWrite::SetWithItemExpiryManual => {
+                FrameCost {
+                    per_chunk: 0,
+                    per_item: set_with_expiry_len(mode, expiry_len),
+                    repeats_key: false,
+                }
+            }
+        }
     }
 }
 
-/// Builds a pipeline that performs `MSET` followed by individual `EXPIREAT` commands.
+/// Builds a pipeline that performs `MSET` followed by one `EXPIRE`-family command per key, as
+/// selected by `mode`, with `jitter` added to each key's TTL.
 pub fn build_mset_with_expire_pipeline<
     K: ToRedisArgs + Sync + Send,
     V: ToRedisArgs + Sync + Send,
@@ -435,17 +1235,21 @@ pub fn build_mset_with_expire_pipeline<
     chunk: &[(K, V)],
     now: DateTime<Utc>,
     ttl: Duration,
+    mode: ExpiryMode,
+    jitter: Option<TtlJitter>,
 ) -> Pipeline {
     let mut pipeline = redis::pipe();
     pipeline.mset(chunk);
-    let expiry_ts = (now + ttl).timestamp();
+    let mut ttls = TtlSampler::new(jitter);
     for (k, _) in chunk {
-        pipeline.expire_at(k, expiry_ts);
+        let expiry = mode.value(now, ttls.ttl(k, ttl));
+        pipeline.cmd(mode.expire_command()).arg(k).arg(expiry);
     }
     pipeline
 }
 
-/// Builds a pipeline that issues `SET key value PX ttl` per entry.
+/// Builds a pipeline that issues `SET key value <EX|PX|EXAT|PXAT> expiry` per entry, with `jitter`
+/// added to each key's TTL.
 pub fn build_set_with_expiry_pipeline<
     K: ToRedisArgs + Sync + Send,
     V: ToRedisArgs + Sync + Send,
@@ -453,17 +1257,21 @@ pub fn build_set_with_expiry_pipeline<
     chunk: &[(K, V)],
     now: DateTime<Utc>,
     ttl: Duration,
+    mode: ExpiryMode,
+    jitter: Option<TtlJitter>,
 ) -> Pipeline {
     let mut pipeline = redis::pipe();
-    let expiry_ts = (now + ttl).timestamp();
+    let mut ttls = TtlSampler::new(jitter);
     for (k, v) in chunk {
-        let opts = SetOptions::default().with_expiration(SetExpiry::EXAT(expiry_ts as u64));
+        let expiry = mode.value(now, ttls.ttl(k, ttl));
+        let opts = SetOptions::default().with_expiration(mode.set_expiry(expiry));
         pipeline.set_options(k, v, opts);
     }
     pipeline
 }
 
-/// Builds a pipeline that issues `SET key value PX ttl` per entry.
+/// Builds a pipeline that issues `SET key value <EX|PX|EXAT|PXAT> expiry` per entry, spelling out
+/// the arguments instead of going through `SetOptions`.
 pub fn build_set_with_expiry_manual_pipeline<
     K: ToRedisArgs + Sync + Send,
     V: ToRedisArgs + Sync + Send,
@@ -471,41 +1279,244 @@ pub fn build_set_with_expiry_manual_pipeline<
     chunk: &[(K, V)],
     now: DateTime<Utc>,
     ttl: Duration,
+    mode: ExpiryMode,
+    jitter: Option<TtlJitter>,
 ) -> Pipeline {
     let mut pipeline = redis::pipe();
-    let expiry_ts = (now + ttl).timestamp();
+    let mut ttls = TtlSampler::new(jitter);
     for (k, v) in chunk {
-        pipeline.cmd("SET").arg(k).arg(v).arg("EXAT").arg(expiry_ts);
+        pipeline
+            .cmd("SET")
+            .arg(k)
+            .arg(v)
+            .arg(mode.set_option())
+            .arg(mode.value(now, ttls.ttl(k, ttl)));
     }
     pipeline
 }
 
+/// Builds a pipeline with a single `EVALSHA` of a Lua script that sets every key of `chunk` with
+/// its expiry atomically, so a dropped connection cannot leave keys without a TTL. The clients
+/// resend it with `EVAL` when the server does not have the script loaded.
+pub fn build_set_with_expiry_script_pipeline<
+    K: ToRedisArgs + Sync + Send,
+    V: ToRedisArgs + Sync + Send,
+>(
+    chunk: &[(K, V)],
+    now: DateTime<Utc>,
+    ttl: Duration,
+    mode: ExpiryMode,
+    jitter: Option<TtlJitter>,
+) -> Pipeline {
+    let mut pipeline = redis::pipe();
+    let eval = pipeline
+        .cmd("EVALSHA")
+        .arg(SET_WITH_EXPIRY.get_hash())
+        .arg(chunk.len());
+    for (k, _) in chunk {
+        eval.arg(k);
+    }
+    eval.arg(mode.set_option());
+    let mut ttls = TtlSampler::new(jitter);
+    for (k, v) in chunk {
+        eval.arg(v).arg(mode.value(now, ttls.ttl(k, ttl)));
+    }
+    pipeline
+}
+
+/// Groups the items of `chunk` by expiry, in order of first appearance.
+fn group_by_expiry<K, V>(chunk: &[(K, V, Expiry)]) -> Vec<(Expiry, Vec<(&K, &V)>)> {
+    let mut groups: Vec<(Expiry, Vec<(&K, &V)>)> = Vec::new();
+    let mut index: HashMap<Expiry, usize> = HashMap::new();
+    for (k, v, expiry) in chunk {
+        let idx = *index.entry(*expiry).or_insert_with(|| {
+            groups.push((*expiry, Vec::new()));
+            groups.len() - 1
+        });
+        groups[idx].1.push((k, v));
+    }
+    groups
+}
+
+/// Per-item expiry counterpart of `build_mset_with_expire_pipeline`: one `MSET` per distinct
+/// expiry in `chunk`, each followed by the `EXPIRE`-family commands of its keys. Keys with
+/// `Expiry::Never` only get the `MSET`.
+pub fn build_mset_with_item_expiry_pipeline<
+    K: ToRedisArgs + Sync + Send,
+    V: ToRedisArgs + Sync + Send,
+>(
+    chunk: &[(K, V, Expiry)],
+    now: DateTime<Utc>,
+    mode: ExpiryMode,
+    jitter: Option<TtlJitter>,
+) -> Pipeline {
+    let mut pipeline = redis::pipe();
+    let mut ttls = TtlSampler::new(jitter);
+    for (expiry, group) in group_by_expiry(chunk) {
+        pipeline.mset(&group);
+        if let Some(ttl) = expiry.ttl(now) {
+            for (k, _) in group {
+                let expiry = mode.value(now, ttls.ttl(k, ttl));
+                pipeline.cmd(mode.expire_command()).arg(k).arg(expiry);
+            }
+        }
+    }
+    pipeline
+}
+
+/// Per-item expiry counterpart of `build_set_with_expiry_pipeline`. Keys with `Expiry::Never` get
+/// a plain `SET`.
+pub fn build_set_with_item_expiry_pipeline<
+    K: ToRedisArgs + Sync + Send,
+    V: ToRedisArgs + Sync + Send,
+>(
+    chunk: &[(K, V, Expiry)],
+    now: DateTime<Utc>,
+    mode: ExpiryMode,
+    jitter: Option<TtlJitter>,
+) -> Pipeline {
+    let mut pipeline = redis::pipe();
+    let mut ttls = TtlSampler::new(jitter);
+    for (k, v, expiry) in chunk {
+        let mut opts = SetOptions::default();
+        if let Some(ttl) = expiry.ttl(now) {
+            let expiry = mode.value(now, ttls.ttl(k, ttl));
+            opts = opts.with_expiration(mode.set_expiry(expiry));
+        }
+        pipeline.set_options(k, v, opts);
+    }
+    pipeline
+}
+
+/// Per-item expiry counterpart of `build_set_with_expiry_manual_pipeline`. Keys with
+/// `Expiry::Never` get a plain `SET`.
+pub fn build_set_with_item_expiry_manual_pipeline<
+    K: ToRedisArgs + Sync + Send,
+    V: ToRedisArgs + Sync + Send,
+>(
+    chunk: &[(K, V, Expiry)],
+    now: DateTime<Utc>,
+    mode: ExpiryMode,
+    jitter: Option<TtlJitter>,
+) -> Pipeline {
+    let mut pipeline = redis::pipe();
+    let mut ttls = TtlSampler::new(jitter);
+    for (k, v, expiry) in chunk {
+        let cmd = pipeline.cmd("SET").arg(k).arg(v);
+        if let Some(ttl) = expiry.ttl(now) {
+            cmd.arg(mode.set_option())
+                .arg(mode.value(now, ttls.ttl(k, ttl)));
+        }
+    }
+    pipeline
+}
+
+/// Builds the connection info for `server`, which is either a `host[:port]` address or a
+/// connection URL:
+///
+/// - `redis://[<username>][:<password>@]<host>[:port][/<db>][?protocol=resp3]`
+/// - `rediss://...` for TLS, with the same components; requires the `tls` feature
+/// - `unix:///path/to/redis.sock[?db=<db>&user=<username>&pass=<password>&protocol=resp3]`
+///
+/// IPv6 literals are accepted in brackets (`[::1]:6379`), or bare when no port is given. The
+/// database, credentials and protocol passed as arguments are used unless the URL sets them.
 pub fn get_connection_info(
     server: String,
     database_slot: i64,
     protocol_version: ProtocolVersion,
     username: Option<String>,
     passwd: Option<String>,
-) -> ConnectionInfo {
-    let conn_addr = {
-        let s = server.split(":").collect::<Vec<&str>>();
-        if s.len() == 2 {
-            ConnectionAddr::Tcp(s[0].to_string(), s[1].parse().unwrap())
-        } else {
-            ConnectionAddr::Tcp(server.clone(), 6379)
+) -> RedisResult<ConnectionInfo> {
+    let defaults = RedisConnectionInfo {
+        db: database_slot,
+        username,
+        password: passwd,
+        protocol: protocol_version,
+    };
+    if server.contains("://") {
+        connection_info_from_url(&server, defaults)
+    } else {
+        Ok(ConnectionInfo {
+            addr: parse_host_port(&server)?,
+            redis: defaults,
+        })
+    }
+}
+
+fn invalid_server(server: &str, reason: impl std::fmt::Display) -> RedisError {
+    RedisError::from((
+        ErrorKind::InvalidClientConfig,
+        "Invalid server address",
+        format!("`{server}`: {reason}"),
+    ))
+}
+
+fn connection_info_from_url(
+    server: &str,
+    defaults: RedisConnectionInfo,
+) -> RedisResult<ConnectionInfo> {
+    let url = redis::parse_redis_url(server).ok_or_else(|| {
+        invalid_server(
+            server,
+            "expected a redis://, rediss:// or unix:// URL with a host or socket path",
+        )
+    })?;
+    let query_has = |name: &str| url.query_pairs().any(|(key, _)| key == name);
+    let is_unix = matches!(url.scheme(), "unix" | "redis+unix" | "valkey+unix");
+    let sets_db = if is_unix {
+        query_has("db")
+    } else {
+        !url.path().trim_matches('/').is_empty()
+    };
+    let sets_protocol = query_has("protocol");
+
+    let mut info = url
+        .into_connection_info()
+        .map_err(|err| invalid_server(server, err))?;
+    if !sets_db {
+        info.redis.db = defaults.db;
+    }
+    if !sets_protocol {
+        info.redis.protocol = defaults.protocol;
+    }
+    info.redis.username = info.redis.username.or(defaults.username);
+    info.redis.password = info.redis.password.or(defaults.password);
+    Ok(info)
+}
+
+fn parse_host_port(server: &str) -> RedisResult<ConnectionAddr> {
+    const DEFAULT_PORT: u16 = 6379;
+
+    let (host, port) = if let Some(rest) = server.strip_prefix('[') {
+        let (host, rest) = rest
+            .split_once(']')
+            .ok_or_else(|| invalid_server(server, "missing `]` after IPv6 address"))?;
+        match rest {
+            "" => (host, None),
+            _ => match rest.strip_prefix(':') {
+                Some(port) => (host, Some(port)),
+                None => return Err(invalid_server(server, "expected `:<port>` after `]`")),
+            },
+        }
+    } else {
+        match server.rsplit_once(':') {
+            // More than one colon without brackets: a bare IPv6 address.
+            Some((host, _)) if host.contains(':') => (server, None),
+            Some((host, port)) => (host, Some(port)),
+            None => (server, None),
         }
     };
 
-    let conn = ConnectionInfo {
-        addr: conn_addr,
-        redis: RedisConnectionInfo {
-            db: database_slot,
-            username: username,
-            password: passwd,
-            protocol: protocol_version,
-        },
+    if host.is_empty() {
+        return Err(invalid_server(server, "missing host"));
+    }
+    let port = match port {
+        Some(port) => port
+            .parse::<u16>()
+            .map_err(|err| invalid_server(server, format!("invalid port `{port}`: {err}")))?,
+        None => DEFAULT_PORT,
     };
-    conn
+    Ok(ConnectionAddr::Tcp(host.to_string(), port))
 }
 
 #[derive(Clone)]
@@ -513,17 +1524,237 @@ pub struct RedisClientFactory {
     pub conn_info: ConnectionInfo,
     pub batch_size: usize,
     pub write_parallelism: usize,
-    pub write_connection_pool_size: usize,
+    pub write_pool: PoolOptions,
+    pub read_parallelism: usize,
+    pub read_pool: PoolOptions,
+    /// Optional cap on the serialized size of a chunk, applied on top of `batch_size`.
+    pub max_chunk_bytes: Option<usize>,
+    pub retry_policy: RetryPolicy,
+    pub expiry_mode: ExpiryMode,
+    pub ttl_jitter: Option<TtlJitter>,
+    /// Whether created clients wrap each pipelined chunk in `MULTI`/`EXEC`.
+    pub transactions: bool,
+    /// When set, created clients tune their write chunk size and parallelism within these bounds.
+    pub adaptive: Option<AdaptiveOptions>,
+    /// When set, the pools of created clients are probed in the background.
+    pub health_check: Option<HealthCheck>,
+    /// When set, `conn_info` is switched to TLS with these settings before connecting.
+    pub tls: Option<TlsOptions>,
+    /// When set, created clients record their calls in these metrics.
+    pub metrics: Option<Metrics>,
 }
 
 impl RedisClientFactory {
     pub async fn create(&self) -> RedisResult<AsyncRedisClientPooled> {
-        AsyncRedisClientPooled::new(
-            self.conn_info.clone(),
+        let conn_info = match &self.tls {
+            Some(tls) => tls.apply(self.conn_info.clone())?,
+            None => self.conn_info.clone(),
+        };
+        let client = AsyncRedisClientPooled::new_with_pools(
+            conn_info,
             self.batch_size,
             self.write_parallelism,
-            self.write_connection_pool_size,
+            self.write_pool,
+            self.read_parallelism,
+            self.read_pool,
         )
-        .await
+        .await?
+        .with_chunking(ChunkingStrategy::new(self.batch_size, self.max_chunk_bytes))
+        .with_retry_policy(self.retry_policy.clone())
+        .with_expiry_mode(self.expiry_mode)
+        .with_transactions(self.transactions)
+        .with_some(self.ttl_jitter, ConfigureClient::with_ttl_jitter)
+        .with_some(self.adaptive, AsyncRedisClientPooled::with_adaptive)
+        .with_some(self.metrics.clone(), ConfigureClient::with_metrics)
+        .with_some(self.health_check, AsyncRedisClientPooled::with_health_check);
+        Ok(client)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn defaults() -> RedisConnectionInfo {
+        RedisConnectionInfo {
+            db: 3,
+            username: Some("user".to_string()),
+            password: Some("secret".to_string()),
+            protocol: ProtocolVersion::RESP3,
+        }
+    }
+
+    fn tcp(host: &str, port: u16) -> ConnectionAddr {
+        ConnectionAddr::Tcp(host.to_string(), port)
+    }
+
+    #[test]
+    fn parses_host_and_port() {
+        assert_eq!(
+            parse_host_port("localhost:6380").unwrap(),
+            tcp("localhost", 6380)
+        );
+        assert_eq!(parse_host_port("10.0.0.1").unwrap(), tcp("10.0.0.1", 6379));
+    }
+
+    #[test]
+    fn parses_ipv6_addresses() {
+        assert_eq!(parse_host_port("[::1]:7000").unwrap(), tcp("::1", 7000));
+        assert_eq!(parse_host_port("[fe80::1]").unwrap(), tcp("fe80::1", 6379));
+        assert_eq!(parse_host_port("fe80::1").unwrap(), tcp("fe80::1", 6379));
+    }
+
+    #[test]
+    fn rejects_malformed_addresses() {
+        for server in [
+            "localhost:http",
+            "localhost:70000",
+            ":6379",
+            "[::1",
+            "[::1]6379",
+            "[]:6379",
+        ] {
+            let err = parse_host_port(server).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidClientConfig, "{server}");
+        }
+    }
+
+    #[test]
+    fn url_settings_override_the_defaults() {
+        let info = connection_info_from_url("redis://admin:pw@cache:6390/5", defaults()).unwrap();
+        assert_eq!(info.addr, tcp("cache", 6390));
+        assert_eq!(info.redis.db, 5);
+        assert_eq!(info.redis.username.as_deref(), Some("admin"));
+        assert_eq!(info.redis.password.as_deref(), Some("pw"));
+        assert_eq!(info.redis.protocol, ProtocolVersion::RESP3);
+    }
+
+    #[test]
+    fn url_without_settings_keeps_the_defaults() {
+        let info = connection_info_from_url("redis://cache", defaults()).unwrap();
+        assert_eq!(info.addr, tcp("cache", 6379));
+        assert_eq!(info.redis.db, 3);
+        assert_eq!(info.redis.username.as_deref(), Some("user"));
+        assert_eq!(info.redis.password.as_deref(), Some("secret"));
+    }
+
+    #[test]
+    fn rejects_unsupported_urls() {
+        let err = connection_info_from_url("http://cache:6379", defaults()).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidClientConfig);
+    }
+
+    #[test]
+    fn get_connection_info_accepts_both_forms() {
+        let url = get_connection_info(
+            "redis://[::1]:7000/2".to_string(),
+            0,
+            ProtocolVersion::RESP2,
+            None,
+            None,
+        );
+        let info = url.unwrap();
+        assert_eq!(info.addr, tcp("::1", 7000));
+        assert_eq!(info.redis.db, 2);
+        let bare = get_connection_info(
+            "[::1]:7000".to_string(),
+            4,
+            ProtocolVersion::RESP2,
+            None,
+            None,
+        );
+        let info = bare.unwrap();
+        assert_eq!(info.addr, tcp("::1", 7000));
+        assert_eq!(info.redis.db, 4);
+    }
+
+    const MODES: [ExpiryMode; 4] = [
+        ExpiryMode::Ex,
+        ExpiryMode::Px,
+        ExpiryMode::ExAt,
+        ExpiryMode::PxAt,
+    ];
+
+    /// Bytes `cost` estimates for `items` and the packed size of `pipeline`.
+    fn frame_sizes<T: ChunkItem>(
+        items: &[T],
+        cost: FrameCost,
+        pipeline: &Pipeline,
+    ) -> (usize, usize) {
+        let estimate = cost.per_chunk + items.iter().map(|item| cost.item_len(item)).sum::<usize>();
+        (estimate, pipeline.get_packed_pipeline().len())
+    }
+
+    #[test]
+    fn frame_costs_cover_the_pipelines() {
+        let items: Vec<(String, String)> = (0..20)
+            .map(|i| (format!("key:{i}"), format!("value:{i}")))
+            .collect();
+        let ttl = Duration::from_secs(3600);
+        for mode in MODES {
+            for kind in [
+                PipelinedWrite::MultiSetWithExpiry,
+                PipelinedWrite::SetWithExpiry,
+                PipelinedWrite::SetWithExpiryManual,
+            ] {
+                let pipeline = kind.build(&items, Utc::now(), ttl, mode, None);
+                let (estimate, packed) = frame_sizes(&items, kind.frame_cost(mode, ttl), &pipeline);
+                // Only the `MSET` header is estimated, by a bound.
+                assert!(
+                    packed <= estimate && estimate - packed < MAX_ARRAY_HEADER_LEN,
+                    "{kind:?} {mode:?}: {packed} bytes estimated as {estimate}"
+                );
+            }
+
+            let kind = PipelinedWrite::SetWithExpiryScript;
+            let pipeline = kind.build(&items, Utc::now(), ttl, mode, None);
+            let fallback = crate::script::eval_fallback(&pipeline);
+            let (estimate, packed) = frame_sizes(&items, kind.frame_cost(mode, ttl), &fallback);
+            assert!(
+                packed <= estimate,
+                "{mode:?}: {packed} bytes estimated as {estimate}"
+            );
+        }
+    }
+
+    #[test]
+    fn item_frame_costs_cover_the_pipelines() {
+        let now = Utc::now();
+        let items: Vec<(String, String, Expiry)> = (0..20)
+            .map(|i| {
+                let expiry = match i % 3 {
+                    0 => Expiry::Never,
+                    1 => Expiry::After(Duration::from_secs(i * 60)),
+                    _ => Expiry::At(now + chrono::Duration::days(i as i64)),
+                };
+                (format!("key:{i}"), format!("value:{i}"), expiry)
+            })
+            .collect();
+        for mode in MODES {
+            for kind in [
+                PipelinedItemWrite::MultiSetWithItemExpiry,
+                PipelinedItemWrite::SetWithItemExpiry,
+                PipelinedItemWrite::SetWithItemExpiryManual,
+            ] {
+                let pipeline = kind.build(&items, now, mode, None);
+                let (estimate, packed) = frame_sizes(&items, kind.frame_cost(mode), &pipeline);
+                assert!(
+                    packed <= estimate,
+                    "{kind:?} {mode:?}: {packed} bytes estimated as {estimate}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn transactions_add_to_the_frame_cost() {
+        let items = [("key", "value")];
+        let ttl = Duration::from_secs(60);
+        let cost = PipelinedWrite::SetWithExpiry.frame_cost(ExpiryMode::Ex, ttl);
+        let mut pipeline =
+            PipelinedWrite::SetWithExpiry.build(&items, Utc::now(), ttl, ExpiryMode::Ex, None);
+        pipeline.atomic();
+        let (estimate, packed) = frame_sizes(&items, cost.with_transaction(), &pipeline);
+        assert_eq!(estimate, packed);
     }
 }
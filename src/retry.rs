@@ -0,0 +1,266 @@
+use rand::Rng;
+use redis::{ErrorKind, RedisError, RedisResult};
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// Decides whether a failed chunk may be sent again.
+pub type RetryClassifier = fn(&RedisError) -> bool;
+
+/// Default classifier: retries connection failures and errors the server reports as transient.
+pub fn is_retryable(err: &RedisError) -> bool {
+    matches!(
+        err.kind(),
+        ErrorKind::IoError
+            | ErrorKind::BusyLoadingError
+            | ErrorKind::TryAgain
+            | ErrorKind::ClusterDown
+            | ErrorKind::MasterDown
+            | ErrorKind::ReadOnly
+    )
+}
+
+/// Retry policy applied to every write chunk.
+///
+/// The delay before attempt `n + 1` is `initial_backoff * multiplier^(n - 1)`, capped at
+/// `max_backoff`, and then reduced by a random fraction of up to `jitter` (`0.0` disables jitter,
+/// `1.0` gives "full jitter").
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Total number of attempts, including the first one. `1` disables retries.
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    pub multiplier: f64,
+    pub jitter: f64,
+    pub classifier: RetryClassifier,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::none()
+    }
+}
+
+impl RetryPolicy {
+    /// A policy that never retries.
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            initial_backoff: Duration::ZERO,
+            max_backoff: Duration::ZERO,
+            multiplier: 1.0,
+            jitter: 0.0,
+            classifier: is_retryable,
+        }
+    }
+
+    /// Exponential backoff starting at 50 ms, doubling up to 2 s, with 50% jitter.
+    pub fn exponential(max_attempts: u32) -> Self {
+        Self {
+            max_attempts: max_attempts.max(1),
+            initial_backoff: Duration::from_millis(50),
+            max_backoff: Duration::from_secs(2),
+            multiplier: 2.0,
+            jitter: 0.5,
+            classifier: is_retryable,
+        }
+    }
+
+    pub fn with_backoff(mut self, initial_backoff: Duration, max_backoff: Duration) -> Self {
+        self.initial_backoff = initial_backoff;
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    pub fn with_jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn with_classifier(mut self, classifier: RetryClassifier) -> Self {
+        self.classifier = classifier;
+        self
+    }
+
+    /// Delay to wait after the given (1-based) failed attempt.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        // Clamped in seconds first: `Duration::mul_f64` panics once the exponential overflows.
+        let exponent = attempt.saturating_sub(1).min(i32::MAX as u32) as i32;
+        let exp = self.multiplier.powi(exponent);
+        let secs =
+            (self.initial_backoff.as_secs_f64() * exp.max(0.0)).min(self.max_backoff.as_secs_f64());
+        let delay = Duration::try_from_secs_f64(secs).unwrap_or(self.max_backoff);
+        if self.jitter <= 0.0 || delay.is_zero() {
+            return delay;
+        }
+        let reduction = rand::rng().random_range(0.0..=self.jitter.min(1.0));
+        delay.mul_f64(1.0 - reduction)
+    }
+
+    /// Runs `operation` until it succeeds, fails with a non-retryable error or runs out of
+    /// attempts.
+    pub async fn retry<T, F, Fut>(&self, context: &str, operation: F) -> RedisResult<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = RedisResult<T>>,
+    {
+        self.retry_until(context, None, operation).await
+    }
+
+    /// `retry` that does not wait past `deadline`: each backoff is cut to the time remaining,
+    /// and once the deadline has passed the last error is returned instead of retrying.
+    pub async fn retry_until<T, F, Fut>(
+        &self,
+        context: &str,
+        deadline: Option<Instant>,
+        mut operation: F,
+    ) -> RedisResult<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = RedisResult<T>>,
+    {
+        let mut attempt = 1;
+        loop {
+            match operation().await {
+                Err(err) if attempt < self.max_attempts && (self.classifier)(&err) => {
+                    let remaining =
+                        deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+                    if remaining.is_some_and(|remaining| remaining.is_zero()) {
+                        return Err(err);
+                    }
+                    let delay = remaining.map_or(self.backoff(attempt), |remaining| {
+                        self.backoff(attempt).min(remaining)
+                    });
+                    warn!(
+                        "Attempt {}/{} of {} failed: {}, retrying in {} ms",
+                        attempt,
+                        self.max_attempts,
+                        context,
+                        err,
+                        delay.as_millis()
+                    );
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(kind: ErrorKind) -> RedisError {
+        RedisError::from((kind, "test error"))
+    }
+
+    #[test]
+    fn backoff_grows_exponentially_up_to_the_cap() {
+        let policy = RetryPolicy::exponential(10)
+            .with_backoff(Duration::from_millis(50), Duration::from_secs(2))
+            .with_jitter(0.0);
+        let delays: Vec<_> = (1..=7).map(|attempt| policy.backoff(attempt)).collect();
+        let expected = [50, 100, 200, 400, 800, 1600, 2000].map(Duration::from_millis);
+        assert_eq!(delays, expected);
+    }
+
+    #[test]
+    fn backoff_does_not_overflow_on_late_attempts() {
+        let policy = RetryPolicy::exponential(u32::MAX).with_jitter(0.0);
+        for attempt in [70, 1_100, u32::MAX] {
+            assert_eq!(policy.backoff(attempt), policy.max_backoff);
+        }
+    }
+
+    #[test]
+    fn backoff_handles_an_infinite_multiplier() {
+        let mut policy = RetryPolicy::exponential(5).with_jitter(0.0);
+        policy.multiplier = f64::INFINITY;
+        assert_eq!(policy.backoff(3), policy.max_backoff);
+    }
+
+    #[test]
+    fn jitter_only_shortens_the_delay() {
+        let policy = RetryPolicy::exponential(5).with_jitter(1.0);
+        for _ in 0..100 {
+            assert!(policy.backoff(3) <= Duration::from_millis(200));
+        }
+    }
+
+    #[test]
+    fn no_backoff_without_retries() {
+        assert_eq!(RetryPolicy::none().backoff(1), Duration::ZERO);
+    }
+
+    #[test]
+    fn retries_transient_errors_only() {
+        for kind in [
+            ErrorKind::IoError,
+            ErrorKind::BusyLoadingError,
+            ErrorKind::TryAgain,
+            ErrorKind::ClusterDown,
+            ErrorKind::MasterDown,
+            ErrorKind::ReadOnly,
+        ] {
+            assert!(is_retryable(&error(kind)), "{kind:?} should be retried");
+        }
+        for kind in [
+            ErrorKind::ResponseError,
+            ErrorKind::TypeError,
+            ErrorKind::ClientError,
+            ErrorKind::NoScriptError,
+            ErrorKind::InvalidClientConfig,
+        ] {
+            assert!(
+                !is_retryable(&error(kind)),
+                "{kind:?} should not be retried"
+            );
+        }
+    }
+
+    /// Policy retrying every error up to 5 times, 1 s apart.
+    fn slow_policy() -> RetryPolicy {
+        RetryPolicy::exponential(5)
+            .with_backoff(Duration::from_secs(1), Duration::from_secs(1))
+            .with_jitter(0.0)
+    }
+
+    async fn failing_attempts(policy: &RetryPolicy, deadline: Option<Instant>) -> u32 {
+        let mut attempts = 0;
+        let result: RedisResult<()> = policy
+            .retry_until("test", deadline, || {
+                attempts += 1;
+                async { Err(error(ErrorKind::IoError)) }
+            })
+            .await;
+        assert!(result.is_err());
+        attempts
+    }
+
+    #[tokio::test]
+    async fn retries_stop_at_the_deadline() {
+        let started = Instant::now();
+        let attempts =
+            failing_attempts(&slow_policy(), Some(started + Duration::from_millis(50))).await;
+        // The backoff is cut to the 50 ms left, and the retry after it is the last one.
+        assert_eq!(attempts, 2);
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn no_retries_once_the_deadline_has_passed() {
+        let started = Instant::now();
+        assert_eq!(failing_attempts(&slow_policy(), Some(started)).await, 1);
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn retries_without_a_deadline_use_every_attempt() {
+        let policy = RetryPolicy::exponential(3)
+            .with_backoff(Duration::from_millis(1), Duration::from_millis(1))
+            .with_jitter(0.0);
+        assert_eq!(failing_attempts(&policy, None).await, 3);
+    }
+}
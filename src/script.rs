@@ -0,0 +1,64 @@
+use redis::{Arg, Cmd, ErrorKind, Pipeline, RedisResult, Script};
+use std::sync::LazyLock;
+use tracing::debug;
+
+/// Sets every key of `KEYS` with its expiry in one atomic step.
+///
+/// `ARGV[1]` is the `SET` expiry option (`EX`, `PX`, `EXAT` or `PXAT`), followed by the value and
+/// the expiry of each key, in the order of `KEYS`. Returns the number of keys written.
+pub(crate) const SET_WITH_EXPIRY_LUA: &str = r"
+local option = ARGV[1]
+for i, key in ipairs(KEYS) do
+    redis.call('SET', key, ARGV[2 * i], option, ARGV[2 * i + 1])
+end
+return #KEYS
+";
+
+/// `SET_WITH_EXPIRY_LUA`, for its SHA1.
+pub(crate) static SET_WITH_EXPIRY: LazyLock<Script> =
+    LazyLock::new(|| Script::new(SET_WITH_EXPIRY_LUA));
+
+/// Executes `pipeline` on `conn`, resending it with `EVAL` when the server does not have the script
+/// it invokes with `EVALSHA`. The `EVAL` loads the script, so later calls find it again.
+pub(crate) async fn exec_with_eval_fallback(
+    pipeline: &Pipeline,
+    conn: &mut impl redis::aio::ConnectionLike,
+) -> RedisResult<()> {
+    match pipeline.exec_async(conn).await {
+        Err(err) if err.kind() == ErrorKind::NoScriptError => {
+            debug!("Script not loaded on the server, falling back to EVAL");
+            eval_fallback(pipeline).exec_async(conn).await
+        }
+        result => result,
+    }
+}
+
+/// Copy of `pipeline` with `EVALSHA` calls of `SET_WITH_EXPIRY` replaced by `EVAL` of its source.
+/// A script that fails with `NOSCRIPT` has not run, so resending the whole pipeline is safe.
+pub(crate) fn eval_fallback(pipeline: &Pipeline) -> Pipeline {
+    let mut fallback = redis::pipe();
+    if pipeline.is_transaction() {
+        fallback.atomic();
+    }
+    for cmd in pipeline.cmd_iter() {
+        if !invokes_script(cmd) {
+            fallback.add_command(cmd.clone());
+            continue;
+        }
+        let eval = fallback.cmd("EVAL").arg(SET_WITH_EXPIRY_LUA);
+        for arg in cmd.args_iter().skip(2) {
+            if let Arg::Simple(arg) = arg {
+                eval.arg(arg);
+            }
+        }
+    }
+    fallback
+}
+
+/// Whether `cmd` is an `EVALSHA` of `SET_WITH_EXPIRY`.
+fn invokes_script(cmd: &Cmd) -> bool {
+    let hash = SET_WITH_EXPIRY.get_hash().as_bytes();
+    let mut args = cmd.args_iter();
+    matches!(args.next(), Some(Arg::Simple(name)) if name.eq_ignore_ascii_case(b"EVALSHA"))
+        && matches!(args.next(), Some(Arg::Simple(arg)) if arg == hash)
+}
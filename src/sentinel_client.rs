@@ -0,0 +1,364 @@
+use crate::call_options::CallOptions;
+use crate::expiry::Expiry;
+use crate::redis_client::{
+    AsyncRedisClient, AsyncRedisClientPooled, PipelinedItemWrite, PipelinedWrite,
+    RedisClientFactory,
+};
+use crate::spans::{call_span, trace_read, trace_write};
+use crate::transaction::CompareAndSet;
+use crate::write_outcome::WriteOutcome;
+use redis::sentinel::{Sentinel, SentinelNodeConnectionInfo};
+use redis::{ConnectionInfo, ErrorKind, FromRedisValue, RedisError, RedisResult, ToRedisArgs};
+use std::ops::Range;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::TaskTracker;
+use tokio_util::task::task_tracker::TaskTrackerToken;
+use tracing::{Span, info, warn};
+
+/// Errors after which the master is re-resolved: the node was demoted, is unreachable or the
+/// connection to it was lost.
+pub fn is_failover_error(err: &RedisError) -> bool {
+    matches!(err.kind(), ErrorKind::ReadOnly | ErrorKind::MasterDown)
+        || err.is_io_error()
+        || err.is_connection_refusal()
+        || err.is_connection_dropped()
+}
+
+struct Master {
+    client: Arc<AsyncRedisClientPooled>,
+    generation: u64,
+    /// Calls using `client`, which are waited for before it is shut down by a failover.
+    calls: TaskTracker,
+}
+
+/// `AsyncRedisClient` that discovers the master of `service_name` through Redis Sentinel and
+/// talks to it with an `AsyncRedisClientPooled` built from `factory`.
+///
+/// When a call fails with a failover error (see `is_failover_error`), the master is resolved
+/// again and the pooled client, including its write connections, is rebuilt. Reads are then
+/// repeated once, and writes repeat only the chunks that failed with a failover error, with the
+/// same `CallOptions`. The replaced client is shut down once no call uses it anymore.
+pub struct AsyncRedisClientSentinel {
+    pub sentinels: Vec<ConnectionInfo>,
+    pub service_name: String,
+    factory: RedisClientFactory,
+    sentinel: Mutex<Sentinel>,
+    master: RwLock<Master>,
+    /// Cancelled by `shutdown`, after which clients built by failovers are shut down as well.
+    shutdown: CancellationToken,
+}
+
+impl AsyncRedisClientSentinel {
+    /// `factory.conn_info.redis` (db, credentials, protocol) is used to connect to the master;
+    /// its address is replaced by whatever the sentinels report.
+    pub async fn new(
+        sentinels: Vec<ConnectionInfo>,
+        service_name: String,
+        factory: RedisClientFactory,
+    ) -> RedisResult<Self> {
+        let mut sentinel = Sentinel::build(sentinels.clone())?;
+        let client = Self::connect_master(&mut sentinel, &service_name, &factory).await?;
+        Ok(Self {
+            sentinels,
+            service_name,
+            factory,
+            sentinel: Mutex::new(sentinel),
+            master: RwLock::new(Master {
+                client: Arc::new(client),
+                generation: 0,
+                calls: TaskTracker::new(),
+            }),
+            shutdown: CancellationToken::new(),
+        })
+    }
+
+    async fn connect_master(
+        sentinel: &mut Sentinel,
+        service_name: &str,
+        factory: &RedisClientFactory,
+    ) -> RedisResult<AsyncRedisClientPooled> {
+        let node_connection_info = SentinelNodeConnectionInfo {
+            tls_mode: None,
+            redis_connection_info: Some(factory.conn_info.redis.clone()),
+        };
+        let master = sentinel
+            .async_master_for(service_name, Some(&node_connection_info))
+            .await?;
+        let mut factory = factory.clone();
+        factory.conn_info = master.get_connection_info().clone();
+        info!(
+            "Resolved master of {} to {}",
+            service_name, factory.conn_info.addr
+        );
+        factory.create().await
+    }
+
+    /// The current master's client and generation, and a token that keeps the client from being
+    /// shut down by a failover while it is held.
+    fn current(&self) -> (Arc<AsyncRedisClientPooled>, u64, TaskTrackerToken) {
+        let master = self.master.read().expect("master lock poisoned");
+        (
+            master.client.clone(),
+            master.generation,
+            master.calls.token(),
+        )
+    }
+
+    /// Re-resolves the master unless another caller already did so since `generation` was
+    /// observed. The previous master's client is shut down once the calls using it are done.
+    pub async fn failover(&self, generation: u64) -> RedisResult<()> {
+        let mut sentinel = self.sentinel.lock().await;
+        if self.current().1 != generation {
+            return Ok(());
+        }
+        let client =
+            Arc::new(Self::connect_master(&mut sentinel, &self.service_name, &self.factory).await?);
+        let (previous, calls) = {
+            let mut master = self.master.write().expect("master lock poisoned");
+            master.generation += 1;
+            (
+                std::mem::replace(&mut master.client, client.clone()),
+                std::mem::take(&mut master.calls),
+            )
+        };
+        retire(previous, calls);
+        // `shutdown` may have run while the new master was resolved.
+        if self.shutdown.is_cancelled() {
+            client.shutdown().await;
+        }
+        Ok(())
+    }
+
+    /// `AsyncRedisClientPooled::shutdown` of the current master's client. Clients built by later
+    /// failovers are shut down as soon as they are.
+    pub async fn shutdown(&self) {
+        self.shutdown.cancel();
+        self.current().0.shutdown().await;
+    }
+
+    /// `AsyncRedisClientPooled::compare_and_set_with_expiry` against the current master. Chunks
+    /// that fail with a failover error are checked and written again on the new master.
+    pub async fn compare_and_set_with_expiry<
+        K: ToRedisArgs + Sync + Send,
+        V: ToRedisArgs + Sync + Send,
+    >(
+        &self,
+        items: &[CompareAndSet<K, V>],
+        ttl: Duration,
+        call: &CallOptions,
+    ) -> WriteOutcome {
+        let span = self.call_span("compare_and_set_with_expiry", items.len(), Some(ttl));
+        self.write(span, items.len(), |client, range| async move {
+            client
+                .compare_and_set_with_expiry(&items[range], ttl, call)
+                .await
+        })
+        .await
+    }
+
+    /// Span of a call, parent of the spans of the pooled client calls it makes.
+    fn call_span(&self, method: &'static str, items: usize, ttl: Option<Duration>) -> Span {
+        call_span(
+            method,
+            &self.server_adder(),
+            items,
+            Some(self.factory.batch_size),
+            ttl,
+        )
+    }
+
+    async fn read<T, F, Fut>(&self, span: Span, op: F) -> RedisResult<T>
+    where
+        F: Fn(Arc<AsyncRedisClientPooled>) -> Fut,
+        Fut: Future<Output = RedisResult<T>>,
+    {
+        trace_read(span, async {
+            let (client, generation, _in_flight) = self.current();
+            match op(client).await {
+                Err(err) if is_failover_error(&err) => {
+                    warn!("Request to {} master failed: {}", self.service_name, err);
+                    self.failover(generation).await?;
+                    let (client, _, _in_flight) = self.current();
+                    op(client).await
+                }
+                result => result,
+            }
+        })
+        .await
+    }
+
+    async fn write<F, Fut>(&self, span: Span, len: usize, op: F) -> WriteOutcome
+    where
+        F: Fn(Arc<AsyncRedisClientPooled>, Range<usize>) -> Fut,
+        Fut: Future<Output = WriteOutcome>,
+    {
+        trace_write(span, self.write_with_failover(len, op)).await
+    }
+
+    async fn write_with_failover<F, Fut>(&self, len: usize, op: F) -> WriteOutcome
+    where
+        F: Fn(Arc<AsyncRedisClientPooled>, Range<usize>) -> Fut,
+        Fut: Future<Output = WriteOutcome>,
+    {
+        let (client, generation, _in_flight) = self.current();
+        let mut outcome = op(client, 0..len).await;
+        if !outcome.failed.iter().any(|f| is_failover_error(&f.error)) {
+            return outcome;
+        }
+
+        if let Err(err) = self.failover(generation).await {
+            warn!(
+                "Failed to re-resolve master of {}: {}",
+                self.service_name, err
+            );
+            return outcome;
+        }
+
+        let (client, _, _in_flight) = self.current();
+        for range in take_failover_ranges(&mut outcome) {
+            let offset = range.start;
+            outcome.extend_shifted(op(client.clone(), range).await, offset);
+        }
+        outcome.finish()
+    }
+}
+
+/// Removes the chunks that failed with a failover error from `outcome` and returns their ranges,
+/// which are to be written again on the new master. Other failures, chunks that were not
+/// attempted or aborted and the reasons a call stopped stay in `outcome`.
+fn take_failover_ranges(outcome: &mut WriteOutcome) -> Vec<Range<usize>> {
+    let (failover, failed): (Vec<_>, Vec<_>) = std::mem::take(&mut outcome.failed)
+        .into_iter()
+        .partition(|failure| is_failover_error(&failure.error));
+    outcome.failed = failed;
+    failover
+        .into_iter()
+        .flat_map(|failure| failure.ranges)
+        .collect()
+}
+
+/// Shuts `client` down once the `calls` using it are done. Calls that started on it before a
+/// failover finish their chunks there; only later calls go to the new master.
+fn retire(client: Arc<AsyncRedisClientPooled>, calls: TaskTracker) {
+    calls.close();
+    tokio::spawn(async move {
+        calls.wait().await;
+        client.shutdown().await;
+    });
+}
+
+impl AsyncRedisClient for AsyncRedisClientSentinel {
+    async fn ping(&self) -> RedisResult<String> {
+        let span = self.call_span("ping", 0, None);
+        self.read(span, |client| async move { client.ping().await })
+            .await
+    }
+
+    async fn multi_get<K: ToRedisArgs + Sync + Send, V: FromRedisValue + Send>(
+        &self,
+        keys: &[K],
+        call: &CallOptions,
+    ) -> RedisResult<Vec<Option<V>>> {
+        let span = self.call_span("multi_get", keys.len(), None);
+        self.read(
+            span,
+            |client| async move { client.multi_get(keys, call).await },
+        )
+        .await
+    }
+
+    async fn multi_set<K: ToRedisArgs + Sync + Send, V: ToRedisArgs + Sync + Send>(
+        &self,
+        items: &[(K, V)],
+        call: &CallOptions,
+    ) -> WriteOutcome {
+        let span = self.call_span("multi_set", items.len(), None);
+        self.write(span, items.len(), |client, range| async move {
+            client.multi_set(&items[range], call).await
+        })
+        .await
+    }
+
+    async fn pipelined_write<K: ToRedisArgs + Sync + Send, V: ToRedisArgs + Sync + Send>(
+        &self,
+        kind: PipelinedWrite,
+        items: &[(K, V)],
+        ttl: Duration,
+        call: &CallOptions,
+    ) -> WriteOutcome {
+        let span = self.call_span(kind.method(), items.len(), Some(ttl));
+        self.write(span, items.len(), |client, range| async move {
+            client.pipelined_write(kind, &items[range], ttl, call).await
+        })
+        .await
+    }
+
+    async fn pipelined_item_write<K: ToRedisArgs + Sync + Send, V: ToRedisArgs + Sync + Send>(
+        &self,
+        kind: PipelinedItemWrite,
+        items: &[(K, V, Expiry)],
+        call: &CallOptions,
+    ) -> WriteOutcome {
+        let span = self.call_span(kind.method(), items.len(), None);
+        self.write(span, items.len(), |client, range| async move {
+            client.pipelined_item_write(kind, &items[range], call).await
+        })
+        .await
+    }
+
+    fn server_adder(&self) -> String {
+        self.current().0.server_adder()
+    }
+}
+
+impl RedisClientFactory {
+    /// Creates a client for the master of `service_name`, discovered through `sentinels`.
+    /// `conn_info.addr` is ignored; the rest of the factory configures the pooled client.
+    pub async fn create_sentinel(
+        &self,
+        sentinels: Vec<ConnectionInfo>,
+        service_name: String,
+    ) -> RedisResult<AsyncRedisClientSentinel> {
+        AsyncRedisClientSentinel::new(sentinels, service_name, self.clone()).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::write_outcome::ChunkFailure;
+
+    fn failure(range: Range<usize>, kind: ErrorKind) -> ChunkFailure {
+        ChunkFailure {
+            ranges: vec![range],
+            error: RedisError::from((kind, "test error")),
+        }
+    }
+
+    #[test]
+    #[allow(clippy::single_range_in_vec_init)]
+    fn only_failover_failures_are_retried() {
+        let mut outcome = WriteOutcome {
+            succeeded: vec![0..2],
+            failed: vec![
+                failure(2..4, ErrorKind::ReadOnly),
+                failure(4..6, ErrorKind::ResponseError),
+                failure(6..8, ErrorKind::IoError),
+            ],
+            not_attempted: vec![8..10],
+            aborted: vec![10..12],
+            deadline_exceeded: true,
+            ..WriteOutcome::default()
+        };
+        assert_eq!(take_failover_ranges(&mut outcome), [2..4, 6..8]);
+        assert_eq!(outcome.succeeded, [0..2]);
+        assert_eq!(outcome.failed.len(), 1);
+        assert_eq!(outcome.failed[0].ranges, [4..6]);
+        assert_eq!(outcome.not_attempted, [8..10]);
+        assert_eq!(outcome.aborted, [10..12]);
+        assert!(outcome.deadline_exceeded);
+    }
+}
@@ -0,0 +1,93 @@
+use crate::write_outcome::WriteOutcome;
+use redis::RedisResult;
+use std::time::Duration;
+use tracing::field::{Empty, Value, display};
+use tracing::{Instrument, Span, debug_span, info_span};
+
+/// Span of one `AsyncRedisClient` call.
+///
+/// `chunks` is recorded once the input has been split, `written` and `failed_chunks` when a write
+/// completes and `error` when a read fails. Chunk spans opened while the call runs are its
+/// children.
+pub(crate) fn call_span(
+    method: &'static str,
+    server: &str,
+    items: usize,
+    batch_size: Option<usize>,
+    ttl: Option<Duration>,
+) -> Span {
+    info_span!(
+        "redis_call",
+        method,
+        server,
+        items,
+        batch_size,
+        ttl_ms = ttl.map(|ttl| ttl.as_millis() as u64),
+        chunks = Empty,
+        written = Empty,
+        failed_chunks = Empty,
+        error = Empty,
+    )
+}
+
+/// Records the number of chunks on the current call span.
+pub(crate) fn record_chunks(chunks: usize) {
+    Span::current().record("chunks", chunks);
+}
+
+/// Records the connection serving the current chunk: the slot index for pooled connections, the
+/// node address for the cluster client. The last attempt wins when a chunk is retried.
+pub(crate) fn record_connection(connection: impl Value) {
+    Span::current().record("connection", connection);
+}
+
+/// Runs a write call inside `span` and records how it ended.
+pub(crate) async fn trace_write(
+    span: Span,
+    write: impl Future<Output = WriteOutcome>,
+) -> WriteOutcome {
+    let outcome = write.instrument(span.clone()).await;
+    span.record("written", outcome.items_written());
+    span.record("failed_chunks", outcome.failed.len());
+    outcome
+}
+
+/// Runs a read call inside `span` and records its error, if any.
+pub(crate) async fn trace_read<T>(
+    span: Span,
+    read: impl Future<Output = RedisResult<T>>,
+) -> RedisResult<T> {
+    let result = read.instrument(span.clone()).await;
+    if let Err(err) = &result {
+        span.record("error", display(err));
+    }
+    result
+}
+
+/// Runs chunk `index` of `items` items inside a child span of the current call and records its
+/// outcome, including retries.
+pub(crate) async fn trace_chunk<T>(
+    index: usize,
+    items: usize,
+    chunk: impl Future<Output = RedisResult<T>>,
+) -> RedisResult<T> {
+    let span = debug_span!(
+        "chunk",
+        index,
+        items,
+        connection = Empty,
+        outcome = Empty,
+        error = Empty,
+    );
+    let result = chunk.instrument(span.clone()).await;
+    match &result {
+        Ok(_) => {
+            span.record("outcome", "ok");
+        }
+        Err(err) => {
+            span.record("outcome", "failed");
+            span.record("error", display(err));
+        }
+    }
+    result
+}
@@ -0,0 +1,78 @@
+use crate::call_options::DEADLINE_EXCEEDED;
+use redis::aio::ConnectionManagerConfig;
+use redis::{AsyncConnectionConfig, ErrorKind, RedisError};
+use std::time::Duration;
+
+/// Connect, response and pool-acquire limits of a client. `None` waits indefinitely, the default
+/// for all of them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Timeouts {
+    /// Limit on establishing a connection, including reconnects.
+    pub connect: Option<Duration>,
+    /// Limit on the response to a single command or pipeline.
+    pub response: Option<Duration>,
+    /// Limit on waiting for a pooled connection to free up. Only used by connection pools.
+    pub acquire: Option<Duration>,
+}
+
+impl Timeouts {
+    pub fn with_connect(mut self, connect: Duration) -> Self {
+        self.connect = Some(connect);
+        self
+    }
+
+    pub fn with_response(mut self, response: Duration) -> Self {
+        self.response = Some(response);
+        self
+    }
+
+    pub fn with_acquire(mut self, acquire: Duration) -> Self {
+        self.acquire = Some(acquire);
+        self
+    }
+
+    pub(crate) fn connection_config(&self) -> AsyncConnectionConfig {
+        let mut config = AsyncConnectionConfig::new();
+        if let Some(connect) = self.connect {
+            config = config.set_connection_timeout(connect);
+        }
+        if let Some(response) = self.response {
+            config = config.set_response_timeout(response);
+        }
+        config
+    }
+
+    pub(crate) fn manager_config(&self) -> ConnectionManagerConfig {
+        let mut config = ConnectionManagerConfig::new();
+        if let Some(connect) = self.connect {
+            config = config.set_connection_timeout(connect);
+        }
+        if let Some(response) = self.response {
+            config = config.set_response_timeout(response);
+        }
+        config
+    }
+}
+
+/// Error of a call whose deadline passed before `not_attempted` of its items were sent.
+///
+/// An extension error with its own code rather than an I/O timeout or a `ClientError`, so that
+/// neither retries nor sentinel failover treat it as a sign of a broken server and callers can
+/// tell it apart with `call_options::is_deadline_exceeded`.
+pub(crate) fn deadline_error(not_attempted: usize) -> RedisError {
+    redis::make_extension_error(
+        DEADLINE_EXCEEDED.to_string(),
+        Some(format!(
+            "Call deadline exceeded, {not_attempted} items not attempted"
+        )),
+    )
+}
+
+/// Error of a caller that waited `waited` for a pooled connection without getting one.
+pub(crate) fn acquire_error(waited: Duration) -> RedisError {
+    RedisError::from((
+        ErrorKind::ClientError,
+        "Timed out waiting for a pooled connection",
+        format!("waited {waited:?}"),
+    ))
+}
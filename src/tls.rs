@@ -0,0 +1,100 @@
+use redis::{ConnectionAddr, ConnectionInfo, ErrorKind, RedisError, RedisResult};
+use std::path::PathBuf;
+
+/// TLS settings applied on top of a `ConnectionInfo`.
+///
+/// Certificates and keys are PEM files. Without `ca_cert` the platform trust store is used.
+/// Connecting requires the crate to be built with the `tls` feature; otherwise `apply` fails.
+#[derive(Debug, Clone, Default)]
+pub struct TlsOptions {
+    /// CA certificate used to verify the server instead of the platform trust store.
+    pub ca_cert: Option<PathBuf>,
+    /// Client certificate chain for mutual TLS. Requires `client_key`.
+    pub client_cert: Option<PathBuf>,
+    /// Private key matching `client_cert`.
+    pub client_key: Option<PathBuf>,
+    /// Skip verification of the server certificate and host name. For development only.
+    pub insecure: bool,
+}
+
+impl TlsOptions {
+    /// Switches a TCP address to `ConnectionAddr::TcpTls` and attaches the configured
+    /// certificates. `rediss://` addresses keep their own `#insecure` flag in addition to
+    /// `self.insecure`.
+    pub fn apply(&self, conn_info: ConnectionInfo) -> RedisResult<ConnectionInfo> {
+        let (host, port, insecure) = match conn_info.addr {
+            ConnectionAddr::Tcp(host, port) => (host, port, self.insecure),
+            ConnectionAddr::TcpTls {
+                host,
+                port,
+                insecure,
+                ..
+            } => (host, port, insecure || self.insecure),
+            addr => {
+                return Err(RedisError::from((
+                    ErrorKind::InvalidClientConfig,
+                    "TLS requires a TCP address",
+                    addr.to_string(),
+                )));
+            }
+        };
+        let conn_info = ConnectionInfo {
+            addr: ConnectionAddr::TcpTls {
+                host,
+                port,
+                insecure,
+                tls_params: None,
+            },
+            redis: conn_info.redis,
+        };
+        self.attach_certificates(conn_info)
+    }
+
+    #[cfg(feature = "tls")]
+    fn attach_certificates(&self, conn_info: ConnectionInfo) -> RedisResult<ConnectionInfo> {
+        if self.ca_cert.is_none() && self.client_cert.is_none() && self.client_key.is_none() {
+            return Ok(conn_info);
+        }
+
+        let client_tls = match (&self.client_cert, &self.client_key) {
+            (Some(cert), Some(key)) => Some(redis::ClientTlsConfig {
+                client_cert: read_pem(cert)?,
+                client_key: read_pem(key)?,
+            }),
+            (None, None) => None,
+            _ => {
+                return Err(RedisError::from((
+                    ErrorKind::InvalidClientConfig,
+                    "A client certificate and a client key must be given together",
+                )));
+            }
+        };
+        let root_cert = self.ca_cert.as_ref().map(read_pem).transpose()?;
+        let certificates = redis::TlsCertificates {
+            client_tls,
+            root_cert,
+        };
+        Ok(redis::Client::build_with_tls(conn_info, certificates)?
+            .get_connection_info()
+            .clone())
+    }
+
+    #[cfg(not(feature = "tls"))]
+    fn attach_certificates(&self, _conn_info: ConnectionInfo) -> RedisResult<ConnectionInfo> {
+        Err(RedisError::from((
+            ErrorKind::InvalidClientConfig,
+            "TLS is not available, the crate was built without the `tls` feature",
+        )))
+    }
+}
+
+#[cfg(feature = "tls")]
+fn read_pem(path: &PathBuf) -> RedisResult<Vec<u8>> {
+    std::fs::read(path).map_err(|err| {
+        RedisError::from((
+            ErrorKind::InvalidClientConfig,
+            "Unable to read PEM file",
+            format!("{}: {err}", path.display()),
+        ))
+    })
+}
@@ -0,0 +1,142 @@
+use crate::call_options::CallOptions;
+use crate::client_config::ClientConfig;
+use crate::metrics::instrument_chunk;
+use crate::redis_client::PipelinedWrite;
+use crate::spans::{record_chunks, trace_chunk};
+use crate::write_outcome::WriteOutcome;
+use redis::aio::MultiplexedConnection;
+use redis::{ConnectionInfo, Pipeline, RedisResult, ToRedisArgs, Value};
+use std::time::Duration;
+use tracing::{debug, warn};
+
+/// An item of a compare-and-set write: `value` is written only while `key` still holds
+/// `expected`, `None` meaning the key must not exist.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompareAndSet<K, V> {
+    pub key: K,
+    pub expected: Option<V>,
+    pub value: V,
+}
+
+impl<K, V> CompareAndSet<K, V> {
+    pub fn new(key: K, expected: Option<V>, value: V) -> Self {
+        Self {
+            key,
+            expected,
+            value,
+        }
+    }
+}
+
+/// Writes one compare-and-set chunk on `conn`: `WATCH`es its keys, checks their current values
+/// and, if all match, runs `pipeline` in `MULTI`/`EXEC`.
+///
+/// Returns `false` when the chunk was not written, either because a key did not hold its expected
+/// value or because a watched key changed before `EXEC` and the server aborted the transaction.
+/// `conn` must not be shared, as `WATCH` applies to the whole connection.
+pub(crate) async fn compare_and_set_chunk<K: ToRedisArgs, V: ToRedisArgs>(
+    conn: &mut MultiplexedConnection,
+    chunk: &[CompareAndSet<K, V>],
+    mut pipeline: Pipeline,
+) -> RedisResult<bool> {
+    let keys: Vec<&K> = chunk.iter().map(|item| &item.key).collect();
+    redis::cmd("WATCH").arg(&keys).exec_async(conn).await?;
+    let current: Vec<Option<Vec<u8>>> = match redis::cmd("MGET").arg(&keys).query_async(conn).await
+    {
+        Ok(current) => current,
+        Err(err) => {
+            let _ = redis::cmd("UNWATCH").exec_async(conn).await;
+            return Err(err);
+        }
+    };
+    let matches = chunk.iter().zip(&current).all(|(item, current)| {
+        item.expected.as_ref().map(|v| v.to_redis_args().concat()) == *current
+    });
+    if !matches {
+        redis::cmd("UNWATCH").exec_async(conn).await?;
+        return Ok(false);
+    }
+    let result: Value = pipeline.atomic().query_async(conn).await?;
+    Ok(result != Value::Nil)
+}
+
+/// Writes `items` with `ttl` chunk by chunk with `compare_and_set_chunk`, on a connection opened
+/// for the call with the timeouts of `config`, using its chunking, expiry settings and metrics.
+/// Chunks not started before the gate of `call` closes are not attempted, and neither are the
+/// chunks after one that fails with an IO or other connection error, as the connection may still
+/// be watching keys or be gone.
+pub(crate) async fn compare_and_set<K: ToRedisArgs + Sync + Send, V: ToRedisArgs + Sync + Send>(
+    conn_info: &ConnectionInfo,
+    config: &ClientConfig,
+    items: &[CompareAndSet<K, V>],
+    ttl: Duration,
+    call: &CallOptions,
+    method: &'static str,
+) -> WriteOutcome {
+    let gate = config.gate(call);
+    let mut outcome = WriteOutcome::default();
+    if items.is_empty() {
+        return outcome;
+    }
+    let conn = match redis::Client::open(conn_info.clone()) {
+        Ok(client) => {
+            client
+                .get_multiplexed_async_connection_with_config(&config.timeouts.connection_config())
+                .await
+        }
+        Err(err) => Err(err),
+    };
+    let mut conn = match conn {
+        Ok(conn) => conn,
+        Err(err) => {
+            warn!("Failed to open a connection for {}: {}", method, err);
+            outcome.record(0..items.len(), Err(err));
+            return outcome;
+        }
+    };
+
+    let cost = config.transaction_frame_cost(PipelinedWrite::SetWithExpiry, ttl);
+    let chunks: Vec<_> = config.chunking.split(items, cost).collect();
+    record_chunks(chunks.len());
+    let mut chunks = chunks.into_iter().enumerate();
+    for (index, (range, chunk)) in chunks.by_ref() {
+        if let Some(stop) = gate.closed() {
+            outcome.record_stopped(range, stop);
+            continue;
+        }
+        let pairs: Vec<(&K, &V)> = chunk.iter().map(|item| (&item.key, &item.value)).collect();
+        let pipeline = config.pipeline(PipelinedWrite::SetWithExpiry, &pairs, ttl);
+        let result = instrument_chunk(
+            config.metrics.as_ref(),
+            method,
+            "compare-and-set",
+            trace_chunk(
+                index,
+                chunk.len(),
+                compare_and_set_chunk(&mut conn, chunk, pipeline),
+            ),
+        )
+        .await;
+        match &result {
+            Ok(true) => {}
+            Ok(false) => debug!("Aborted compare-and-set of {} items", chunk.len()),
+            Err(err) => warn!(
+                "Failed to sync {} features via compare-and-set: {}",
+                chunk.len(),
+                err
+            ),
+        }
+        let connection_lost = matches!(
+            &result,
+            Err(err) if err.is_io_error() || err.is_unrecoverable_error()
+        );
+        outcome.record_transaction(range, result);
+        if connection_lost {
+            break;
+        }
+    }
+    outcome
+        .not_attempted
+        .extend(chunks.map(|(_, (range, _))| range));
+    outcome.finish()
+}
@@ -0,0 +1,177 @@
+use crate::cancellation::Stop;
+use redis::{RedisError, RedisResult};
+use std::ops::Range;
+
+/// A chunk that failed to be written, identified by the item indices it covered.
+///
+/// Chunks of the single-node clients cover one contiguous range; chunks of the cluster client
+/// group items by hash slot and may cover several.
+#[derive(Debug)]
+pub struct ChunkFailure {
+    pub ranges: Vec<Range<usize>>,
+    pub error: RedisError,
+}
+
+impl ChunkFailure {
+    /// Indices of the items in the failed chunk.
+    pub fn indices(&self) -> impl Iterator<Item = usize> {
+        self.ranges.iter().cloned().flatten()
+    }
+}
+
+/// Collapses sorted item indices into contiguous ranges.
+pub(crate) fn index_runs(indices: &[usize]) -> Vec<Range<usize>> {
+    let mut runs: Vec<Range<usize>> = Vec::new();
+    for &idx in indices {
+        match runs.last_mut() {
+            Some(last) if last.end == idx => last.end = idx + 1,
+            _ => runs.push(idx..idx + 1),
+        }
+    }
+    runs
+}
+
+/// Per-chunk report of a chunked write.
+///
+/// All ranges are indices into the slice passed to the write method and are sorted by start.
+/// `not_attempted` lists chunks that were never sent, e.g. because an earlier chunk failed on a
+/// client that stops at the first error, because the call's deadline passed, which also sets
+/// `deadline_exceeded`, because the call was cancelled, which sets `cancelled`, or because the
+/// client was shut down, which sets `shut_down`. Chunks that were already in flight when the call
+/// stopped still finish and are reported as usual. `aborted` lists compare-and-set chunks that
+/// were not written because a key did not hold its expected value or changed before `EXEC`.
+#[derive(Debug, Default)]
+pub struct WriteOutcome {
+    pub succeeded: Vec<Range<usize>>,
+    pub failed: Vec<ChunkFailure>,
+    pub not_attempted: Vec<Range<usize>>,
+    pub aborted: Vec<Range<usize>>,
+    pub deadline_exceeded: bool,
+    pub cancelled: bool,
+    pub shut_down: bool,
+}
+
+impl WriteOutcome {
+    pub(crate) fn record(&mut self, range: Range<usize>, result: RedisResult<()>) {
+        self.record_ranges(vec![range], result);
+    }
+
+    pub(crate) fn record_ranges(&mut self, ranges: Vec<Range<usize>>, result: RedisResult<()>) {
+        match result {
+            Ok(()) => self.succeeded.extend(ranges),
+            Err(error) => self.failed.push(ChunkFailure { ranges, error }),
+        }
+    }
+
+    /// Records a chunk that was not sent because the call stopped for `stop`.
+    pub(crate) fn record_stopped(&mut self, range: Range<usize>, stop: Stop) {
+        self.not_attempted.push(range);
+        match stop {
+            Stop::DeadlineExceeded => self.deadline_exceeded = true,
+            Stop::Cancelled => self.cancelled = true,
+            Stop::ShutDown => self.shut_down = true,
+        }
+    }
+
+    /// Records a compare-and-set chunk: `Ok(false)` means its transaction was aborted.
+    pub(crate) fn record_transaction(&mut self, range: Range<usize>, result: RedisResult<bool>) {
+        match result {
+            Ok(false) => self.aborted.push(range),
+            result => self.record(range, result.map(|_| ())),
+        }
+    }
+
+    /// Merges the outcome of a write over `items[offset..]` into this one.
+    pub(crate) fn extend_shifted(&mut self, other: WriteOutcome, offset: usize) {
+        let shift = |r: Range<usize>| r.start + offset..r.end + offset;
+        self.succeeded
+            .extend(other.succeeded.into_iter().map(shift));
+        self.failed
+            .extend(other.failed.into_iter().map(|f| ChunkFailure {
+                ranges: f.ranges.into_iter().map(shift).collect(),
+                error: f.error,
+            }));
+        self.not_attempted
+            .extend(other.not_attempted.into_iter().map(shift));
+        self.aborted.extend(other.aborted.into_iter().map(shift));
+        self.deadline_exceeded |= other.deadline_exceeded;
+        self.cancelled |= other.cancelled;
+        self.shut_down |= other.shut_down;
+    }
+
+    pub(crate) fn finish(mut self) -> Self {
+        self.succeeded.sort_by_key(|r| r.start);
+        self.failed
+            .sort_by_key(|f| f.ranges.first().map_or(0, |r| r.start));
+        self.not_attempted.sort_by_key(|r| r.start);
+        self.aborted.sort_by_key(|r| r.start);
+        self
+    }
+
+    /// Returns `true` when every chunk was written.
+    pub fn is_success(&self) -> bool {
+        self.failed.is_empty() && self.not_attempted.is_empty() && self.aborted.is_empty()
+    }
+
+    /// Number of items that were acknowledged by the server.
+    pub fn items_written(&self) -> usize {
+        self.succeeded.iter().map(|r| r.len()).sum()
+    }
+
+    /// Ranges of items that still have to be written: failed, not attempted and aborted chunks,
+    /// sorted by start and with adjacent ranges merged.
+    pub fn pending_ranges(&self) -> Vec<Range<usize>> {
+        let mut ranges: Vec<Range<usize>> = self
+            .failed
+            .iter()
+            .flat_map(|f| f.ranges.iter().cloned())
+            .chain(self.not_attempted.iter().cloned())
+            .chain(self.aborted.iter().cloned())
+            .collect();
+        ranges.sort_by_key(|r| r.start);
+
+        let mut merged: Vec<Range<usize>> = Vec::with_capacity(ranges.len());
+        for range in ranges {
+            match merged.last_mut() {
+                Some(last) if last.end == range.start => last.end = range.end,
+                _ => merged.push(range),
+            }
+        }
+        merged
+    }
+
+    /// Indices of all items that still have to be written.
+    pub fn pending_indices(&self) -> impl Iterator<Item = usize> {
+        self.pending_ranges().into_iter().flatten()
+    }
+
+    /// Collapses the outcome into the error of the first failed chunk, if any, or else a
+    /// shutdown, cancellation or deadline error counting the items that were not attempted.
+    pub fn into_result(self) -> RedisResult<()> {
+        let stop = self.stop();
+        if let Some(failure) = self.failed.into_iter().next() {
+            return Err(failure.error);
+        }
+        match stop {
+            Some(stop) => {
+                let not_attempted = self.not_attempted.iter().map(|r| r.len()).sum();
+                Err(stop.error(not_attempted))
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Why the call stopped starting chunks, if it did: shutdown wins over cancellation, which
+    /// wins over the deadline.
+    pub(crate) fn stop(&self) -> Option<Stop> {
+        if self.shut_down {
+            Some(Stop::ShutDown)
+        } else if self.cancelled {
+            Some(Stop::Cancelled)
+        } else if self.deadline_exceeded {
+            Some(Stop::DeadlineExceeded)
+        } else {
+            None
+        }
+    }
+}